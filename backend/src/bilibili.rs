@@ -1,5 +1,7 @@
 use std::fmt;
+use std::sync::Arc;
 
+use async_trait::async_trait;
 use reqwest::get;
 use serde::{Deserialize, Deserializer, Serialize};
 
@@ -11,7 +13,7 @@ struct Response<T> {
 }
 
 #[derive(Debug, thiserror::Error)]
-enum Error {
+pub enum Error {
     #[error(transparent)]
     Bilbili(#[from] BilbiliError),
     #[error(transparent)]
@@ -21,7 +23,7 @@ enum Error {
 }
 
 #[derive(Clone, Debug, Serialize, thiserror::Error)]
-struct BilbiliError {
+pub struct BilbiliError {
     code: i32,
     message: String,
 }
@@ -45,6 +47,25 @@ pub struct PostInfo {
     pub content: String,
 }
 
+/// The parts of the bilibili API used by the handlers, so that tests can
+/// run without network access.
+#[async_trait]
+pub trait BilibiliClient: Send + Sync {
+    async fn post(&self, rid: u64) -> Result<PostInfo, Error>;
+}
+
+pub type SharedBilibili = Arc<dyn BilibiliClient>;
+
+/// The public bilibili API.
+pub struct BilibiliApi;
+
+#[async_trait]
+impl BilibiliClient for BilibiliApi {
+    async fn post(&self, rid: u64) -> Result<PostInfo, Error> {
+        PostInfo::get_by_id(rid).await
+    }
+}
+
 impl<T> Response<T> {
     fn success(&self) -> bool {
         self.code == 0
@@ -109,6 +130,19 @@ impl PostInfo {
 }
 
 impl Error {
+    /// What bilibili answers when the requested object does not exist.
+    pub fn not_found() -> Self {
+        Self::Bilbili(BilbiliError {
+            code: -404,
+            message: "啥都木有".to_string(),
+        })
+    }
+
+    /// Whether bilibili reported that the requested object does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::Bilbili(e) if e.code == -404)
+    }
+
     pub fn unwrap_bilibili(self) -> BilbiliError {
         if let Self::Bilbili(b) = self {
             b
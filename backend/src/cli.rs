@@ -0,0 +1,326 @@
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use sqlx::PgPool;
+use vtuber_quiz_commons::models::*;
+
+use crate::config::Argon2;
+use crate::{db, migrate, webhook};
+
+#[derive(Debug, Parser)]
+#[clap(about, version)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start the HTTP server (default)
+    Serve,
+    /// Manage database migrations
+    Migrate {
+        #[clap(subcommand)]
+        action: MigrateAction,
+    },
+    /// Manage users
+    User {
+        #[clap(subcommand)]
+        action: UserAction,
+    },
+    /// Manage questions
+    Question {
+        #[clap(subcommand)]
+        action: QuestionAction,
+    },
+    /// Recompute reputation from votes, for one user or everyone
+    RecomputeReputation {
+        /// only recompute for this user
+        username: Option<String>,
+    },
+    /// Export users, questions, votes, followings and applications as JSON
+    ///
+    /// Password hashes and challenge codes are not exported.
+    Export {
+        /// write to this file instead of stdout
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Receive webhook deliveries locally, printing those signed with the
+    /// secret
+    ///
+    /// Needs neither the configuration nor the database, to try out a bot
+    /// before registering its webhook.
+    WebhookReceiver {
+        #[clap(long, default_value = "127.0.0.1:8099")]
+        bind: String,
+        /// secret returned when the webhook was registered
+        #[clap(long)]
+        secret: String,
+        /// answer every delivery with 500, to watch the retries
+        #[clap(long)]
+        fail: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateAction {
+    /// Apply all pending migrations
+    Up,
+    /// Show applied and pending migrations
+    Status,
+    /// Print the migrations `up` would apply, without applying them
+    DryRun,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UserAction {
+    /// Create a user, the password is read from stdin unless given
+    Create {
+        username: String,
+        #[clap(long)]
+        password: Option<String>,
+        #[clap(long, default_value = "normal")]
+        role: UserRole,
+    },
+    /// Set the role of a user
+    SetRole { username: String, role: UserRole },
+    /// Block a user
+    Block { username: String },
+    /// Unblock a user
+    Unblock { username: String },
+    /// Reset the password of a user, the password is read from stdin unless given
+    ResetPassword {
+        username: String,
+        #[clap(long)]
+        password: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum QuestionAction {
+    /// Soft-delete a question
+    Delete { qid: i32 },
+    /// Restore a soft-deleted question
+    Restore { qid: i32 },
+}
+
+#[derive(Serialize)]
+struct Export {
+    users: Vec<User>,
+    questions: Vec<Question>,
+    votes: Vec<Vote>,
+    following: Vec<Following>,
+    apply_to: Vec<Application>,
+}
+
+pub async fn migrate(pool: &PgPool, action: MigrateAction) -> anyhow::Result<()> {
+    match action {
+        MigrateAction::Up => {
+            migrate::run(pool).await?;
+            println!("database is up to date");
+        }
+        MigrateAction::Status => {
+            let (status, dirty) = migrate::status(pool).await?;
+            for s in status {
+                let state = match s.state {
+                    migrate::State::Applied => "applied",
+                    migrate::State::Pending => "pending",
+                    migrate::State::ChecksumMismatch => "checksum mismatch",
+                };
+                println!("{} {:<18} {}", s.version, state, s.description);
+            }
+            if let Some(version) = dirty {
+                println!("migration {} is partially applied", version);
+            }
+        }
+        MigrateAction::DryRun => {
+            let pending = migrate::pending(pool).await?;
+            if pending.is_empty() {
+                println!("no pending migrations");
+            }
+            for m in pending {
+                println!("-- {}/{}", m.version, m.description);
+                println!("{}", m.sql.trim_end());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn user(pool: &PgPool, argon2: &Argon2, action: UserAction) -> anyhow::Result<()> {
+    match action {
+        UserAction::Create {
+            username,
+            password,
+            role,
+        } => {
+            let password = read_password(password)?;
+            let id = db::create_user(pool, &username, &password, argon2).await?;
+            if role != UserRole::Normal {
+                db::set_user_role(pool, id, role).await?;
+            }
+            println!("created user {} with id {}", username, id);
+        }
+        UserAction::SetRole { username, role } => {
+            let user = db::get_user_by_username(pool, &username).await?;
+            db::set_user_role(pool, user.id, role).await?;
+            println!("set role of {} to {}", username, role);
+        }
+        UserAction::Block { username } => {
+            let user = db::get_user_by_username(pool, &username).await?;
+            db::set_user_blocked(pool, user.id, true).await?;
+            println!("blocked {}", username);
+        }
+        UserAction::Unblock { username } => {
+            let user = db::get_user_by_username(pool, &username).await?;
+            db::set_user_blocked(pool, user.id, false).await?;
+            println!("unblocked {}", username);
+        }
+        UserAction::ResetPassword { username, password } => {
+            let user = db::get_user_by_username(pool, &username).await?;
+            let password = read_password(password)?;
+            db::set_password(pool, user.id, &password, argon2).await?;
+            println!("reset password of {}", username);
+        }
+    }
+    Ok(())
+}
+
+pub async fn question(pool: &PgPool, action: QuestionAction) -> anyhow::Result<()> {
+    match action {
+        QuestionAction::Delete { qid } => {
+            let question = db::get_question(pool, qid).await?;
+            db::delete_question(pool, qid).await?;
+            db::notify_moderation(
+                pool,
+                question.creator,
+                NotificationKind::QuestionDeleted,
+                qid,
+            )
+            .await?;
+            println!("deleted question {}", qid);
+        }
+        QuestionAction::Restore { qid } => {
+            let question = db::get_question(pool, qid).await?;
+            db::restore_question(pool, qid).await?;
+            db::notify_moderation(
+                pool,
+                question.creator,
+                NotificationKind::QuestionRestored,
+                qid,
+            )
+            .await?;
+            println!("restored question {}", qid);
+        }
+    }
+    Ok(())
+}
+
+pub async fn recompute_reputation(pool: &PgPool, username: Option<String>) -> anyhow::Result<()> {
+    let uid = match username {
+        Some(username) => Some(db::get_user_by_username(pool, &username).await?.id),
+        None => None,
+    };
+    let updated = db::recompute_reputation(pool, uid).await?;
+    println!("recomputed reputation of {} user(s)", updated);
+    Ok(())
+}
+
+pub async fn export(pool: &PgPool, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let export = Export {
+        users: db::list_users(pool).await?,
+        questions: db::list_questions(pool).await?,
+        votes: db::list_votes(pool).await?,
+        following: db::list_followings(pool).await?,
+        apply_to: db::list_applications(pool).await?,
+    };
+    match output {
+        Some(path) => {
+            let file =
+                File::create(&path).with_context(|| format!("cannot create {}", path.display()))?;
+            serde_json::to_writer_pretty(file, &export)?;
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            serde_json::to_writer_pretty(&mut stdout, &export)?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_password(password: Option<String>) -> anyhow::Result<String> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let password = line.trim_end_matches(&['\r', '\n'][..]).to_string();
+    anyhow::ensure!(!password.is_empty(), "empty password");
+    Ok(password)
+}
+
+pub async fn webhook_receiver(bind: String, secret: String, fail: bool) -> anyhow::Result<()> {
+    let receiver = web::Data::new(Receiver { secret, fail });
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(receiver.clone())
+            .default_service(web::to(receive))
+    })
+    .workers(1)
+    .bind(&bind)?;
+    println!("receiving webhook deliveries on http://{}", bind);
+    server.run().await?;
+    Ok(())
+}
+
+struct Receiver {
+    secret: String,
+    fail: bool,
+}
+
+async fn receive(
+    req: HttpRequest,
+    body: web::Bytes,
+    receiver: web::Data<Receiver>,
+) -> HttpResponse {
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+    };
+    let verified = header(webhook::TIMESTAMP)
+        .parse()
+        .map(|timestamp| {
+            webhook::verify(
+                &receiver.secret,
+                timestamp,
+                &body,
+                header(webhook::SIGNATURE),
+            )
+        })
+        .unwrap_or(false);
+    if !verified {
+        eprintln!("rejected a delivery with an invalid signature");
+        return HttpResponse::Unauthorized().finish();
+    }
+    println!(
+        "delivery {} ({}): {}",
+        header(webhook::DELIVERY),
+        header(webhook::EVENT),
+        String::from_utf8_lossy(&body)
+    );
+    if receiver.fail {
+        HttpResponse::InternalServerError().finish()
+    } else {
+        HttpResponse::NoContent().finish()
+    }
+}
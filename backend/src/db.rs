@@ -1,24 +1,88 @@
 use std::convert::TryFrom;
 
+use chrono::{DateTime, Utc};
 use rand::{thread_rng, Rng, RngCore};
 use sqlx::postgres::PgDatabaseError;
 use sqlx::PgPool;
 use vtuber_quiz_commons::models::*;
 
-use crate::error::Error;
+use crate::config;
+use crate::error::{Error, FieldError};
+use crate::rate_limit::Bucket;
 
 #[derive(Debug, thiserror::Error)]
 pub enum PgError {
-    #[error("unique_violation")]
-    UniqueViolation,
-    #[error("check_violation")]
-    CheckViolation,
-    #[error("other error")]
-    Others,
+    #[error("unique_violation: {0}")]
+    UniqueViolation(Box<PgDetail>),
+    #[error("check_violation: {0}")]
+    CheckViolation(Box<PgDetail>),
+    #[error("foreign_key_violation: {0}")]
+    ForeignKeyViolation(Box<PgDetail>),
+    #[error("not_null_violation: {0}")]
+    NotNullViolation(Box<PgDetail>),
+    /// `string_data_right_truncation`, a value too long for a `varchar(n)`.
+    /// Postgres does not report the column for this one.
+    #[error("string_data_right_truncation: {0}")]
+    StringTruncation(Box<PgDetail>),
+    #[error("other error: {0}")]
+    Others(Box<PgDetail>),
 }
 
-pub async fn create_user(pool: &PgPool, username: &str, password: &str) -> Result<i32, Error> {
-    let hashed = hash_password(password.as_bytes());
+impl PgError {
+    pub fn detail(&self) -> &PgDetail {
+        use PgError::*;
+
+        match self {
+            UniqueViolation(d)
+            | CheckViolation(d)
+            | ForeignKeyViolation(d)
+            | NotNullViolation(d)
+            | StringTruncation(d)
+            | Others(d) => d,
+        }
+    }
+
+    /// Name of the violated constraint, e.g. `user_username_key`.
+    pub fn constraint(&self) -> Option<&str> {
+        self.detail().constraint.as_deref()
+    }
+
+    /// Column of a not-null violation.
+    pub fn column(&self) -> Option<&str> {
+        self.detail().column.as_deref()
+    }
+}
+
+/// What Postgres reported about a failed statement. Only meant for logs.
+#[derive(Clone, Debug, Default, thiserror::Error)]
+#[error("[{code}] {message}")]
+pub struct PgDetail {
+    /// SQLSTATE
+    pub code: String,
+    pub message: String,
+    pub detail: Option<String>,
+    pub table: Option<String>,
+    pub column: Option<String>,
+    pub constraint: Option<String>,
+}
+
+impl PgDetail {
+    pub fn new(code: &str, message: &str) -> Self {
+        Self {
+            code: code.to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+pub async fn create_user(
+    pool: &PgPool,
+    username: &str,
+    password: &str,
+    params: &config::Argon2,
+) -> Result<i32, Error> {
+    let hashed = hash_password(password.as_bytes(), params)?;
     query!(
         r#"
 insert into "user" (username, password)
@@ -33,7 +97,12 @@ returning id"#,
     .map_err(|e: sqlx::Error| -> Error {
         match PgError::try_from(e) {
             Ok(pg) => match pg {
-                PgError::UniqueViolation => Error::ConflictUsername,
+                PgError::UniqueViolation(_) if pg.constraint() == Some("user_username_key") => {
+                    Error::ConflictUsername
+                }
+                PgError::StringTruncation(_) => {
+                    Error::invalid("username", "must be at most 20 characters")
+                }
                 _ => pg.into(),
             },
             Err(e) => e,
@@ -86,7 +155,7 @@ pub async fn create_or_replace_challenge(pool: &PgPool, id: i32) -> Result<Strin
             Ok(_) => return Ok(challenge),
             Err(e) => match PgError::try_from(e) {
                 Ok(pg) => match pg {
-                    PgError::UniqueViolation => continue,
+                    PgError::UniqueViolation(_) => continue,
                     _ => return Err(pg.into()),
                 },
                 Err(e) => return Err(e),
@@ -95,23 +164,333 @@ pub async fn create_or_replace_challenge(pool: &PgPool, id: i32) -> Result<Strin
     }
 }
 
-pub async fn login(pool: &PgPool, username: &str, password: &str) -> Result<User, Error> {
+pub async fn get_bilibili(pool: &PgPool, user: i32) -> Result<Bilibili, Error> {
+    query_as!(
+        Bilibili,
+        r#"select uid, name, avatar, "user", created from bilibili where "user" = $1"#,
+        user
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Bind a bilibili account to a user, replacing the account bound before,
+/// and clear the challenge code used to verify it.
+pub async fn bind_bilibili(
+    pool: &PgPool,
+    user: i32,
+    uid: i64,
+    name: &str,
+    avatar: &str,
+) -> Result<(), Error> {
+    let mut tx = pool.begin().await?;
+    query!(r#"delete from bilibili where "user" = $1"#, user)
+        .execute(&mut tx)
+        .await?;
+    query!(
+        r#"insert into bilibili (uid, name, avatar, "user") values ($1, $2, $3, $4)"#,
+        uid,
+        name,
+        avatar,
+        user
+    )
+    .execute(&mut tx)
+    .await
+    .map_err(|e: sqlx::Error| -> Error {
+        match PgError::try_from(e) {
+            Ok(pg) => match pg {
+                PgError::UniqueViolation(_) => Error::ConflictBilibili,
+                _ => pg.into(),
+            },
+            Err(e) => e,
+        }
+    })?;
+    query!(
+        r#"update "user" set challenge = null, updated = current_timestamp where id = $1"#,
+        user
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Minutes a recovery code stays valid.
+pub const RECOVERY_TTL_MINUTES: i32 = 30;
+
+/// Start recovering the account of a user, returning its unexpired recovery
+/// code if any, so that others starting a recovery cannot replace the code the
+/// user is about to post.
+pub async fn create_recovery(pool: &PgPool, user: i32) -> Result<String, Error> {
+    // the selects see the recovery from before the upsert, so exactly one of
+    // them returns a code
+    query!(
+        r#"
+with created as (
+    insert into recovery ("user", code, expires)
+    values ($1, $2, current_timestamp + make_interval(mins => $3))
+    on conflict ("user") do update
+    set code = excluded.code, created = current_timestamp, expires = excluded.expires
+    where recovery.expires <= current_timestamp
+    returning code
+)
+select code as "code!" from created
+union all
+select code from recovery where "user" = $1 and expires > current_timestamp"#,
+        user,
+        generate_challenge_code(),
+        RECOVERY_TTL_MINUTES
+    )
+    .fetch_one(pool)
+    .await
+    .map(|r| r.code)
+    .map_err(|e| e.into())
+}
+
+/// The recovery code of a user, unless it has expired.
+pub async fn get_recovery_code(pool: &PgPool, user: i32) -> Result<String, Error> {
+    query!(
+        r#"select code from recovery where "user" = $1 and expires > current_timestamp"#,
+        user
+    )
+    .fetch_one(pool)
+    .await
+    .map(|r| r.code)
+    .map_err(|e| e.into())
+}
+
+pub async fn delete_recovery(pool: &PgPool, user: i32) -> Result<(), Error> {
+    query!(r#"delete from recovery where "user" = $1"#, user)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn login_blocked_secs(pool: &PgPool, key: &str) -> Result<Option<u64>, Error> {
+    let secs = query!(
+        r#"
+select ceil(extract(epoch from blocked_until - current_timestamp))::bigint as "secs!"
+from login_attempt
+where key = $1 and blocked_until > current_timestamp"#,
+        key
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| r.secs.max(1) as u64);
+    Ok(secs)
+}
+
+/// Consecutive failed logins for `key`, if the last one is at most
+/// `window_secs` ago.
+pub async fn login_failures(pool: &PgPool, key: &str, window_secs: u64) -> Result<u32, Error> {
+    let failures = query!(
+        r#"
+select failures
+from login_attempt
+where key = $1 and last_failure > current_timestamp - make_interval(secs => $2)"#,
+        key,
+        window_secs as f64
+    )
+    .fetch_optional(pool)
+    .await?
+    .map_or(0, |r| r.failures as u32);
+    Ok(failures)
+}
+
+/// Count a failed login for `key`, starting over if the last failure is more
+/// than `reset_secs` ago, and return the number of consecutive failures.
+pub async fn record_login_failure(pool: &PgPool, key: &str, reset_secs: u64) -> Result<u32, Error> {
+    let failures = query!(
+        r#"
+insert into login_attempt (key, failures)
+values ($1, 1)
+on conflict (key) do update
+set failures = case
+        when login_attempt.last_failure > current_timestamp - make_interval(secs => $2)
+        then login_attempt.failures + 1
+        else 1
+    end,
+    last_failure = current_timestamp
+returning failures"#,
+        key,
+        reset_secs as f64
+    )
+    .fetch_one(pool)
+    .await?
+    .failures;
+    Ok(failures as u32)
+}
+
+/// Refuse logins for `key` during the next `secs` seconds.
+pub async fn block_login(pool: &PgPool, key: &str, secs: u64) -> Result<(), Error> {
+    query!(
+        r#"
+update login_attempt
+set blocked_until = current_timestamp + make_interval(secs => $2)
+where key = $1"#,
+        key,
+        secs as f64
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn clear_login_failures(pool: &PgPool, key: &str) -> Result<(), Error> {
+    query!("delete from login_attempt where key = $1", key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Take a token from the bucket `key`, see [`Bucket::take`]. The row is locked
+/// so that concurrent requests, possibly on other instances, are counted.
+pub async fn take_token(pool: &PgPool, key: &str, bucket: Bucket) -> Result<Option<u64>, Error> {
+    let mut tx = pool.begin().await?;
+    query!(
+        "insert into rate_limit (key, tokens) values ($1, $2) on conflict (key) do nothing",
+        key,
+        bucket.capacity as f64
+    )
+    .execute(&mut tx)
+    .await?;
+    let row = query!(
+        r#"
+select tokens, extract(epoch from current_timestamp - updated)::float8 as "elapsed!"
+from rate_limit
+where key = $1
+for update"#,
+        key
+    )
+    .fetch_one(&mut tx)
+    .await?;
+    let (tokens, wait) = bucket.take(row.tokens, row.elapsed);
+    query!(
+        "update rate_limit set tokens = $2, updated = current_timestamp where key = $1",
+        key,
+        tokens
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(wait)
+}
+
+/// Check the password of a user, upgrading its hash to `params` if needed.
+pub async fn login(
+    pool: &PgPool,
+    username: &str,
+    password: &str,
+    params: &config::Argon2,
+) -> Result<User, Error> {
     match get_user_by_username(pool, username).await {
         Ok(user) => {
-            if verify_password(user.password.as_str(), password.as_bytes()) {
-                Ok(user)
-            } else {
-                Err(Error::InvalidCredential)
+            if !verify_password(user.password.as_str(), password.as_bytes())? {
+                return Err(Error::InvalidCredential);
+            }
+            if needs_rehash(user.password.as_str(), params) {
+                set_password(pool, user.id, password, params).await?;
             }
+            Ok(user)
         }
-        Err(Error::Sqlx(sqlx::Error::RowNotFound)) => Err(Error::InvalidCredential),
+        Err(Error::NotFound) => Err(Error::InvalidCredential),
         Err(e) => Err(e),
     }
 }
 
+pub async fn set_user_role(pool: &PgPool, id: i32, role: UserRole) -> Result<(), Error> {
+    query!(
+        r#"update "user" set role = $1, updated = current_timestamp where id = $2"#,
+        role as _,
+        id
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+pub async fn set_user_blocked(pool: &PgPool, id: i32, blocked: bool) -> Result<(), Error> {
+    query!(
+        r#"update "user" set blocked = $1, updated = current_timestamp where id = $2"#,
+        blocked,
+        id
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+pub async fn set_password(
+    pool: &PgPool,
+    id: i32,
+    password: &str,
+    params: &config::Argon2,
+) -> Result<(), Error> {
+    let hashed = hash_password(password.as_bytes(), params)?;
+    query!(
+        r#"update "user" set password = $1, updated = current_timestamp where id = $2"#,
+        hashed,
+        id
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+/// Recompute reputation from the votes on the questions a user created,
+/// for one user or, if `uid` is `None`, for all users.
+///
+/// An up vote counts 1, a down vote or an incorrect flag counts -1, votes on
+/// deleted questions are ignored. Returns the number of users updated.
+pub async fn recompute_reputation(pool: &PgPool, uid: Option<i32>) -> Result<u64, Error> {
+    let res = query!(
+        r#"
+update "user" u
+set reputation = coalesce((
+    select sum(case v.action
+                   when 'up_vote' then 1
+                   when 'down_vote' then -1
+                   when 'flag_incorrect' then -1
+                   else 0
+               end)
+    from vote v
+    join question q on q.id = v.question
+    where q.creator = u.id and not q.deleted
+), 0)
+where $1::integer is null or u.id = $1"#,
+        uid
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn list_users(pool: &PgPool) -> Result<Vec<User>, Error> {
+    let users = query_as!(
+        User,
+        r#"
+select id, username, password, challenge, blocked, role as "role: UserRole", reputation, created, updated
+from "user"
+order by id"#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(users)
+}
+
 pub async fn follow(pool: &PgPool, from: i32, to: i32, private: bool) -> Result<(), Error> {
     match query!(
-        r#"insert into following (follower, followee, private) values ($1, $2, $3)"#,
+        r#"
+with f as (
+    insert into following (follower, followee, private) values ($1, $2, $3)
+    returning follower, followee, private
+)
+insert into notification (recipient, kind, actor)
+select followee, 'followed', follower from f where not private"#,
         from,
         to,
         private
@@ -122,7 +501,11 @@ pub async fn follow(pool: &PgPool, from: i32, to: i32, private: bool) -> Result<
         Ok(_) => Ok(()),
         Err(e) => match PgError::try_from(e) {
             Ok(pg) => match pg {
-                PgError::UniqueViolation => Ok(()),
+                PgError::UniqueViolation(_) => Ok(()),
+                PgError::ForeignKeyViolation(_) => Err(Error::NotFound),
+                PgError::CheckViolation(_) if pg.constraint() == Some("following_check") => Err(
+                    Error::BadPath(FieldError::new("id", "cannot follow yourself")),
+                ),
                 _ => Err(pg.into()),
             },
             Err(e) => Err(e),
@@ -130,6 +513,18 @@ pub async fn follow(pool: &PgPool, from: i32, to: i32, private: bool) -> Result<
     }
 }
 
+/// Ids of every user followed by `user`, private follows included.
+pub async fn followee_ids(pool: &PgPool, user: i32) -> Result<Vec<i32>, Error> {
+    query!(
+        r#"select followee from following where follower = $1"#,
+        user
+    )
+    .fetch_all(pool)
+    .await
+    .map(|rows| rows.into_iter().map(|r| r.followee).collect())
+    .map_err(|e| e.into())
+}
+
 pub async fn unfollow(pool: &PgPool, from: i32, to: i32) -> Result<(), Error> {
     query!(
         r#"delete from following where follower = $1 and followee = $2"#,
@@ -142,6 +537,139 @@ pub async fn unfollow(pool: &PgPool, from: i32, to: i32) -> Result<(), Error> {
     Ok(())
 }
 
+/// Followers of `user` as seen by `viewer`, newest first.
+pub async fn list_followers(
+    pool: &PgPool,
+    user: i32,
+    viewer: Option<i32>,
+    offset: i64,
+    limit: i64,
+) -> Result<FollowList, Error> {
+    let total = query!(
+        r#"
+select count(*) as "count!"
+from following
+where followee = $1 and (not private or follower = $2)"#,
+        user,
+        viewer
+    )
+    .fetch_one(pool)
+    .await?
+    .count;
+    let follows = query_as!(
+        FollowEntry,
+        r#"
+select u.id, u.username, f.private, f.created
+from following f
+join "user" u on u.id = f.follower
+where f.followee = $1 and (not f.private or f.follower = $2)
+order by f.created desc, u.id
+offset $3
+limit $4"#,
+        user,
+        viewer,
+        offset,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(FollowList { total, follows })
+}
+
+/// Users followed by `user` as seen by `viewer`, newest first.
+pub async fn list_followees(
+    pool: &PgPool,
+    user: i32,
+    viewer: Option<i32>,
+    offset: i64,
+    limit: i64,
+) -> Result<FollowList, Error> {
+    let total = query!(
+        r#"
+select count(*) as "count!"
+from following
+where follower = $1 and (not private or follower = $2)"#,
+        user,
+        viewer
+    )
+    .fetch_one(pool)
+    .await?
+    .count;
+    let follows = query_as!(
+        FollowEntry,
+        r#"
+select u.id, u.username, f.private, f.created
+from following f
+join "user" u on u.id = f.followee
+where f.follower = $1 and (not f.private or f.follower = $2)
+order by f.created desc, u.id
+offset $3
+limit $4"#,
+        user,
+        viewer,
+        offset,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(FollowList { total, follows })
+}
+
+pub async fn follow_counts(
+    pool: &PgPool,
+    user: i32,
+    viewer: Option<i32>,
+) -> Result<FollowCounts, Error> {
+    query_as!(
+        FollowCounts,
+        r#"
+select
+    (select count(*) from following where followee = $1 and (not private or follower = $2))
+        as "followers!",
+    (select count(*) from following where follower = $1 and (not private or follower = $2))
+        as "followees!""#,
+        user,
+        viewer
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Public profile of a user, with follow counts as seen by `viewer`.
+pub async fn get_profile(pool: &PgPool, id: i32, viewer: Option<i32>) -> Result<Profile, Error> {
+    let row = query!(
+        r#"
+select u.id, u.username, u.role as "role: UserRole", u.reputation, u.created,
+    b.name as "bilibili_name?", b.avatar as "bilibili_avatar?",
+    (select count(*) from question q where q.creator = u.id and not q.draft and not q.deleted)
+        as "questions!",
+    (select count(*)
+        from apply_to a join question q on q.id = a.question
+        where a.vtuber = u.id and a.status = 'approved' and not q.draft and not q.deleted)
+        as "applied_questions!"
+from "user" u
+left join bilibili b on b."user" = u.id
+where u.id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+    let follow_counts = follow_counts(pool, id, viewer).await?;
+    Ok(Profile {
+        id: row.id,
+        username: row.username,
+        role: row.role,
+        reputation: row.reputation,
+        bilibili_name: row.bilibili_name,
+        bilibili_avatar: row.bilibili_avatar,
+        questions: row.questions,
+        follow_counts,
+        applied_questions: (row.role == UserRole::Vtuber).then_some(row.applied_questions),
+        created: row.created,
+    })
+}
+
 pub async fn create_question(
     pool: &PgPool,
     creator: i32,
@@ -150,9 +678,15 @@ pub async fn create_question(
     let content = &question.content;
     query!(
         r#"
-insert into question (creator, description, choices, answer, "type", audiences, draft)
-values ($1, $2, $3, $4, $5, $6, $7)
-returning id"#,
+with q as (
+    insert into question (creator, description, choices, answer, "type", audiences, draft)
+    values ($1, $2, $3, $4, $5, $6, $7)
+    returning id, creator, draft
+), e as (
+    insert into event (kind, actor, question)
+    select 'question_published', creator, id from q where not draft
+)
+select id as "id!" from q"#,
         creator,
         content.description(),
         &content.choices(),
@@ -164,21 +698,28 @@ returning id"#,
     .fetch_one(pool)
     .await
     .map(|res| res.id)
-    .map_err(|e| e.into())
+    .map_err(question_error)
 }
 
 pub async fn update_question(pool: &PgPool, question: Question) -> Result<(), Error> {
     query!(
         r#"
-update question
-set
-    description = $1,
-    choices = $2,
-    answer = $3,
-    audiences = $4,
-    draft = $5,
-    updated = current_timestamp
-where id = $6"#,
+with q as (
+    update question
+    set
+        description = $1,
+        choices = $2,
+        answer = $3,
+        audiences = $4,
+        draft = $5,
+        updated = current_timestamp
+    where id = $6
+    returning id, creator, draft
+)
+insert into event (kind, actor, question)
+select 'question_published', creator, id from q
+where not draft
+  and not exists (select 1 from event where kind = 'question_published' and question = q.id)"#,
         question.description,
         &question.choices,
         &question.answer,
@@ -188,8 +729,22 @@ where id = $6"#,
     )
     .execute(pool)
     .await
-    .map(|_| ())?;
-    Ok(())
+    .map(|_| ())
+    .map_err(question_error)
+}
+
+/// Errors of statements writing a question.
+fn question_error(e: sqlx::Error) -> Error {
+    match PgError::try_from(e) {
+        Ok(pg) => match pg {
+            PgError::ForeignKeyViolation(_) => Error::NotFound,
+            PgError::StringTruncation(_) => {
+                Error::invalid("audiences", "must be at most 10 characters each")
+            }
+            _ => pg.into(),
+        },
+        Err(e) => e,
+    }
 }
 
 pub async fn get_question(pool: &PgPool, qid: i32) -> Result<Question, Error> {
@@ -217,21 +772,125 @@ pub async fn delete_question(pool: &PgPool, qid: i32) -> Result<(), Error> {
     Ok(())
 }
 
+pub async fn restore_question(pool: &PgPool, qid: i32) -> Result<(), Error> {
+    query!(r#"update question set deleted = false where id = $1"#, qid)
+        .execute(pool)
+        .await
+        .map(|_| ())?;
+    Ok(())
+}
+
+pub async fn list_questions(pool: &PgPool) -> Result<Vec<Question>, Error> {
+    query_as!(
+        Question,
+        r#"
+select id, creator, description, choices, answer,
+       "type" as "question_type: QuestionType",
+       audiences as "audiences: Vec<String>",
+       draft, deleted, created, updated
+from question
+order by id"#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn list_votes(pool: &PgPool) -> Result<Vec<Vote>, Error> {
+    query_as!(
+        Vote,
+        r#"select id, voter, question, action as "action: VoteAction", created from vote order by id"#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn list_followings(pool: &PgPool) -> Result<Vec<Following>, Error> {
+    query_as!(
+        Following,
+        r#"select follower, followee, private, created from following order by created"#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn list_applications(pool: &PgPool) -> Result<Vec<Application>, Error> {
+    query_as!(
+        Application,
+        r#"
+select question, vtuber, status as "status: ApplicationStatus", pinned, created, reviewed
+from apply_to
+order by created"#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
 pub async fn apply_question_to_vtuber(pool: &PgPool, qid: i32, uid: i32) -> Result<(), Error> {
     query!(
-        r#"insert into apply_to (question, vtuber) values ($1, $2) on conflict do nothing"#,
+        r#"
+with a as (
+    -- the vtuber's own questions need no review
+    insert into apply_to (question, vtuber, status) values ($1, $2, case
+        when (select creator from question where id = $1) = $2 then 'approved'::application_status
+        else 'pending'::application_status
+    end)
+    on conflict do nothing
+    returning question, vtuber, status
+), e as (
+    -- followers hear of other applications once approved
+    insert into event (kind, actor, question, vtuber)
+    select 'question_applied', q.creator, a.question, a.vtuber
+    from a join question q on q.id = a.question
+    where a.status = 'approved'
+    returning question, vtuber
+), d as (
+    -- webhooks are told of the same applications as the feed
+    insert into webhook_delivery (webhook, event, payload)
+    select w.id, 'question_applied', json_build_object(
+        'question', q.id, 'description', q.description, 'creator', q.creator, 'vtuber', e.vtuber
+    )::text
+    from e
+    join question q on q.id = e.question
+    join webhook w on w.owner = e.vtuber
+)
+insert into notification (recipient, kind, actor, question)
+select a.vtuber, 'applied', q.creator, a.question
+from a join question q on q.id = a.question
+where a.vtuber <> q.creator"#,
         qid,
         uid
     )
     .execute(pool)
     .await
-    .map(|_| ())?;
-    Ok(())
+    .map(|_| ())
+    .map_err(|e: sqlx::Error| -> Error {
+        match PgError::try_from(e) {
+            Ok(pg) => match pg {
+                PgError::ForeignKeyViolation(_) => Error::NotFound,
+                _ => pg.into(),
+            },
+            Err(e) => e,
+        }
+    })
 }
 
+/// Take back an application. Rejected and hidden applications are kept, so
+/// that applying again does not undo the review.
 pub async fn remove_question_to_vtuber(pool: &PgPool, qid: i32, uid: i32) -> Result<(), Error> {
     query!(
-        r#"delete from apply_to where question = $1 and vtuber = $2"#,
+        r#"
+with a as (
+    delete from apply_to
+    where question = $1 and vtuber = $2 and status in ('pending', 'approved')
+    returning question, vtuber
+)
+delete from event e
+using a
+where e.kind = 'question_applied' and e.question = a.question and e.vtuber = a.vtuber"#,
         qid,
         uid
     )
@@ -241,51 +900,959 @@ pub async fn remove_question_to_vtuber(pool: &PgPool, qid: i32, uid: i32) -> Res
     Ok(())
 }
 
-pub async fn get_question_applied(pool: &PgPool, qid: i32) -> Vec<i32> {
-    use futures::future::ready;
-    use futures_util::StreamExt;
-    query!(r#"select vtuber from apply_to where question = $1"#, qid)
-        .fetch(pool) // impl Stream<Item = sqlx::Result<{adhoc struct}>>
-        .filter_map(|res| ready(res.ok().map(|r| r.vtuber)))
-        .collect()
-        .await
+pub async fn get_application(pool: &PgPool, qid: i32, vtuber: i32) -> Result<Application, Error> {
+    query_as!(
+        Application,
+        r#"
+select question, vtuber, status as "status: ApplicationStatus", pinned, created, reviewed
+from apply_to
+where question = $1 and vtuber = $2"#,
+        qid,
+        vtuber
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.into())
 }
 
-pub async fn vote_to_question(
+/// Set the status of an application and whether it is pinned, unpinning it
+/// unless approved. The first approval adds the application to the feed and
+/// queues its `question_applied` webhook deliveries.
+pub async fn review_application(
     pool: &PgPool,
-    uid: i32,
     qid: i32,
-    action: VoteAction,
+    vtuber: i32,
+    status: ApplicationStatus,
+    pinned: bool,
 ) -> Result<(), Error> {
-    query!(
-        r#"insert into vote (voter, question, action) values ($1, $2, $3)"#,
-        uid,
+    let reviewed = query!(
+        r#"
+with r as (
+    update apply_to
+    set status = $3, pinned = $4 and $3 = 'approved'::application_status,
+        reviewed = current_timestamp
+    where question = $1 and vtuber = $2
+    returning question, vtuber, status
+), e as (
+    insert into event (kind, actor, question, vtuber)
+    select 'question_applied', q.creator, r.question, r.vtuber
+    from r join question q on q.id = r.question
+    where r.status = 'approved' and not exists (
+        select 1 from event
+        where kind = 'question_applied' and question = r.question and vtuber = r.vtuber
+    )
+    returning question, vtuber
+), d as (
+    insert into webhook_delivery (webhook, event, payload)
+    select w.id, 'question_applied', json_build_object(
+        'question', q.id, 'description', q.description, 'creator', q.creator, 'vtuber', e.vtuber
+    )::text
+    from e
+    join question q on q.id = e.question
+    join webhook w on w.owner = e.vtuber
+)
+select count(*) as "count!" from r"#,
         qid,
-        action as _
+        vtuber,
+        status as ApplicationStatus,
+        pinned
     )
-    .execute(pool)
-    .await
-    .map(|_| ())?;
+    .fetch_one(pool)
+    .await?
+    .count;
+    if reviewed == 0 {
+        return Err(Error::NotFound);
+    }
+    Ok(())
+}
+
+/// Published questions applied to `vtuber`, only those in `status` if
+/// given, pinned first and then newest first.
+pub async fn list_vtuber_applications(
+    pool: &PgPool,
+    vtuber: i32,
+    status: Option<ApplicationStatus>,
+    offset: i64,
+    limit: i64,
+) -> Result<ApplicationList, Error> {
+    let total = query!(
+        r#"
+select count(*) as "count!"
+from apply_to a join question q on q.id = a.question
+where a.vtuber = $1 and ($2::application_status is null or a.status = $2)
+    and not q.draft and not q.deleted"#,
+        vtuber,
+        status as Option<ApplicationStatus>
+    )
+    .fetch_one(pool)
+    .await?
+    .count;
+    let applications = query_as!(
+        ApplicationEntry,
+        r#"
+select a.question, q.description, q.creator, u.username as creator_name,
+    a.status as "status: ApplicationStatus", a.pinned, a.created, a.reviewed
+from apply_to a
+join question q on q.id = a.question
+join "user" u on u.id = q.creator
+where a.vtuber = $1 and ($2::application_status is null or a.status = $2)
+    and not q.draft and not q.deleted
+order by a.pinned desc, a.created desc, a.question desc
+offset $3
+limit $4"#,
+        vtuber,
+        status as Option<ApplicationStatus>,
+        offset,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(ApplicationList {
+        total,
+        applications,
+    })
+}
+
+/// Events of the users followed by `user`, and of questions applied to the
+/// vtubers followed by `user`, older than `before` and newest first.
+/// Events of drafts and deleted questions, and of applications no longer
+/// approved by their vtuber, are left out.
+pub async fn feed(
+    pool: &PgPool,
+    user: i32,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Feed, Error> {
+    let events = query_as!(
+        FeedEvent,
+        r#"
+select e.id, e.kind as "kind: EventKind", e.actor, a.username as actor_name,
+    e.question, e.vtuber, v.username as "vtuber_name?", e.attempt, t.quiz_set as "quiz_set?",
+    s.name as "quiz_set_name?", t.score, t.passed, e.created
+from event e
+join "user" a on a.id = e.actor
+left join "user" v on v.id = e.vtuber
+left join quiz_attempt t on t.id = e.attempt
+left join quiz_set s on s.id = t.quiz_set
+left join question q on q.id = e.question
+where (e.actor in (select followee from following where follower = $1)
+        or e.vtuber in (select followee from following where follower = $1))
+    and (q.id is null or (not q.draft and not q.deleted))
+    and (s.id is null or not s.draft)
+    and not exists (
+        select 1 from apply_to a
+        where a.question = e.question and a.vtuber = e.vtuber
+            and a.status <> 'approved'
+    )
+    and ($2::bigint is null or e.id < $2)
+order by e.id desc
+limit $3"#,
+        user,
+        before,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    let next = match events.last() {
+        Some(last) if events.len() as i64 == limit => Some(last.id),
+        _ => None,
+    };
+    Ok(Feed { events, next })
+}
+
+pub async fn get_event(pool: &PgPool, id: i64) -> Result<FeedEvent, Error> {
+    query_as!(
+        FeedEvent,
+        r#"
+select e.id, e.kind as "kind: EventKind", e.actor, a.username as actor_name,
+    e.question, e.vtuber, v.username as "vtuber_name?", e.attempt, t.quiz_set as "quiz_set?",
+    s.name as "quiz_set_name?", t.score, t.passed, e.created
+from event e
+join "user" a on a.id = e.actor
+left join "user" v on v.id = e.vtuber
+left join quiz_attempt t on t.id = e.attempt
+left join quiz_set s on s.id = t.quiz_set
+where e.id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Every event, oldest first.
+pub async fn list_events(pool: &PgPool) -> Result<Vec<FeedEvent>, Error> {
+    query_as!(
+        FeedEvent,
+        r#"
+select e.id, e.kind as "kind: EventKind", e.actor, a.username as actor_name,
+    e.question, e.vtuber, v.username as "vtuber_name?", e.attempt, t.quiz_set as "quiz_set?",
+    s.name as "quiz_set_name?", t.score, t.passed, e.created
+from event e
+join "user" a on a.id = e.actor
+left join "user" v on v.id = e.vtuber
+left join quiz_attempt t on t.id = e.attempt
+left join quiz_set s on s.id = t.quiz_set
+order by e.id"#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn get_question_applied(pool: &PgPool, qid: i32) -> Result<Vec<i32>, Error> {
+    let rows = query!(
+        r#"select vtuber from apply_to where question = $1 and status = 'approved'"#,
+        qid
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.vtuber).collect())
+}
+
+pub async fn vote_to_question(
+    pool: &PgPool,
+    uid: i32,
+    qid: i32,
+    action: VoteAction,
+) -> Result<(), Error> {
+    query!(
+        r#"
+with v as (
+    insert into vote (voter, question, action) values ($1, $2, $3)
+    returning voter, question, action
+)
+insert into notification (recipient, kind, actor, question, vote)
+select q.creator, 'voted', v.voter, v.question, v.action
+from v join question q on q.id = v.question
+where q.creator <> v.voter"#,
+        uid,
+        qid,
+        action as _
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e: sqlx::Error| -> Error {
+        match PgError::try_from(e) {
+            Ok(pg) => match pg {
+                PgError::UniqueViolation(_) => Error::ConflictVote,
+                PgError::ForeignKeyViolation(_) => Error::NotFound,
+                _ => pg.into(),
+            },
+            Err(e) => e,
+        }
+    })
+}
+
+/// Notify `recipient` of something done to `question` by a moderator.
+pub async fn notify_moderation(
+    pool: &PgPool,
+    recipient: i32,
+    kind: NotificationKind,
+    question: i32,
+) -> Result<(), Error> {
+    query!(
+        r#"insert into notification (recipient, kind, question) values ($1, $2, $3)"#,
+        recipient,
+        kind as _,
+        question
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+/// Notifications of `user` with ids below `before`, newest first.
+pub async fn list_notifications(
+    pool: &PgPool,
+    user: i32,
+    before: Option<i64>,
+    limit: i64,
+    unread_only: bool,
+) -> Result<NotificationList, Error> {
+    let notifications = query_as!(
+        Notification,
+        r#"
+select n.id, n.kind as "kind: NotificationKind", n.actor, a.username as "actor_name?",
+    n.question, n.vote as "vote: VoteAction", n.read, n.created
+from notification n
+left join "user" a on a.id = n.actor
+where n.recipient = $1
+    and ($2::bigint is null or n.id < $2)
+    and (not $4 or not n.read)
+order by n.id desc
+limit $3"#,
+        user,
+        before,
+        limit,
+        unread_only
+    )
+    .fetch_all(pool)
+    .await?;
+    let next = match notifications.last() {
+        Some(last) if notifications.len() as i64 == limit => Some(last.id),
+        _ => None,
+    };
+    Ok(NotificationList {
+        notifications,
+        next,
+    })
+}
+
+/// A notification and its recipient.
+pub async fn get_notification(pool: &PgPool, id: i64) -> Result<(i32, Notification), Error> {
+    let row = query!(
+        r#"
+select n.id, n.recipient, n.kind as "kind: NotificationKind", n.actor,
+    a.username as "actor_name?", n.question, n.vote as "vote: VoteAction", n.read, n.created
+from notification n
+left join "user" a on a.id = n.actor
+where n.id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+    let notification = Notification {
+        id: row.id,
+        kind: row.kind,
+        actor: row.actor,
+        actor_name: row.actor_name,
+        question: row.question,
+        vote: row.vote,
+        read: row.read,
+        created: row.created,
+    };
+    Ok((row.recipient, notification))
+}
+
+pub async fn unread_notifications(pool: &PgPool, user: i32) -> Result<i64, Error> {
+    query!(
+        r#"select count(*) as "count!" from notification where recipient = $1 and not read"#,
+        user
+    )
+    .fetch_one(pool)
+    .await
+    .map(|row| row.count)
+    .map_err(|e| e.into())
+}
+
+/// Mark the notifications of `user` given by `ids` as read, or all of them.
+/// Ids of notifications of other users are ignored.
+pub async fn mark_notifications_read(
+    pool: &PgPool,
+    user: i32,
+    ids: Option<&[i64]>,
+) -> Result<(), Error> {
+    query!(
+        r#"
+update notification set read = true
+where recipient = $1 and not read and ($2::bigint[] is null or id = any($2))"#,
+        user,
+        ids
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+pub async fn create_quiz_set(
+    pool: &PgPool,
+    owner: i32,
+    req: &QuizSetRequest,
+) -> Result<i32, Error> {
+    let mut tx = pool.begin().await?;
+    let id = query!(
+        r#"
+insert into quiz_set (owner, vtuber, name, description, time_limit_secs, pass_mark, draft)
+values ($1, $2, $3, $4, $5, $6, $7)
+returning id"#,
+        owner,
+        req.vtuber,
+        req.name,
+        req.description,
+        req.time_limit_secs,
+        req.pass_mark,
+        req.draft
+    )
+    .fetch_one(&mut tx)
+    .await
+    .map_err(map_quiz_set_error)?
+    .id;
+    set_quiz_set_questions(&mut tx, id, &req.questions).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// Replace a quiz set, bumping its version.
+pub async fn update_quiz_set(pool: &PgPool, id: i32, req: &QuizSetRequest) -> Result<(), Error> {
+    let mut tx = pool.begin().await?;
+    let result = query!(
+        r#"
+update quiz_set
+set vtuber = $2, name = $3, description = $4, time_limit_secs = $5, pass_mark = $6,
+    draft = $7, version = version + 1, updated = current_timestamp
+where id = $1"#,
+        id,
+        req.vtuber,
+        req.name,
+        req.description,
+        req.time_limit_secs,
+        req.pass_mark,
+        req.draft
+    )
+    .execute(&mut tx)
+    .await
+    .map_err(map_quiz_set_error)?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+    query!(r#"delete from quiz_set_question where quiz_set = $1"#, id)
+        .execute(&mut tx)
+        .await?;
+    set_quiz_set_questions(&mut tx, id, &req.questions).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn set_quiz_set_questions(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    id: i32,
+    questions: &[i32],
+) -> Result<(), Error> {
+    query!(
+        r#"
+insert into quiz_set_question (quiz_set, position, question)
+select $1, q.position - 1, q.question
+from unnest($2::integer[]) with ordinality as q(question, position)"#,
+        id,
+        questions
+    )
+    .execute(tx)
+    .await
+    .map_err(map_quiz_set_error)?;
     Ok(())
 }
 
-fn hash_password(password: &[u8]) -> String {
+fn map_quiz_set_error(e: sqlx::Error) -> Error {
+    match PgError::try_from(e) {
+        Ok(pg) => match pg {
+            PgError::ForeignKeyViolation(_) => Error::NotFound,
+            PgError::UniqueViolation(_) => {
+                Error::invalid("questions", "must not contain a question twice")
+            }
+            _ => pg.into(),
+        },
+        Err(e) => e,
+    }
+}
+
+pub async fn get_quiz_set(pool: &PgPool, id: i32) -> Result<QuizSet, Error> {
+    query_as!(
+        QuizSet,
+        r#"
+select s.id, s.owner, s.vtuber, s.owner = s.vtuber as "official!", s.name, s.description,
+    array(select question from quiz_set_question where quiz_set = s.id order by position)
+        as "questions!",
+    s.version, s.time_limit_secs, s.pass_mark, s.draft, s.created, s.updated
+from quiz_set s
+where s.id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Delete a quiz set along with its attempts.
+pub async fn delete_quiz_set(pool: &PgPool, id: i32) -> Result<(), Error> {
+    let result = query!(r#"delete from quiz_set where id = $1"#, id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+    Ok(())
+}
+
+/// Quiz sets about `vtuber`, or owned by `owner`, with drafts only if
+/// `drafts`. Official quiz sets first, then most recently updated first.
+pub async fn list_quiz_sets(
+    pool: &PgPool,
+    vtuber: Option<i32>,
+    owner: Option<i32>,
+    drafts: bool,
+    offset: i64,
+    limit: i64,
+) -> Result<QuizSetList, Error> {
+    let total = query!(
+        r#"
+select count(*) as "count!"
+from quiz_set
+where ($1::integer is null or vtuber = $1) and ($2::integer is null or owner = $2)
+    and ($3 or not draft)"#,
+        vtuber,
+        owner,
+        drafts
+    )
+    .fetch_one(pool)
+    .await?
+    .count;
+    let quiz_sets = query_as!(
+        QuizSet,
+        r#"
+select s.id, s.owner, s.vtuber, s.owner = s.vtuber as "official!", s.name, s.description,
+    array(select question from quiz_set_question where quiz_set = s.id order by position)
+        as "questions!",
+    s.version, s.time_limit_secs, s.pass_mark, s.draft, s.created, s.updated
+from quiz_set s
+where ($1::integer is null or s.vtuber = $1) and ($2::integer is null or s.owner = $2)
+    and ($3 or not s.draft)
+order by s.owner = s.vtuber desc, s.updated desc, s.id desc
+offset $4
+limit $5"#,
+        vtuber,
+        owner,
+        drafts,
+        offset,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(QuizSetList { total, quiz_sets })
+}
+
+/// Whether a published quiz set asks the question.
+pub async fn question_in_quiz_set(pool: &PgPool, qid: i32) -> Result<bool, Error> {
+    let row = query!(
+        r#"
+select exists (
+    select 1
+    from quiz_set_question q
+    join quiz_set s on s.id = q.quiz_set
+    where q.question = $1 and not s.draft
+) as "asked!"
+"#,
+        qid
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.asked)
+}
+
+/// Start an attempt of a quiz set as of `version` and `pass_mark`, asking
+/// `questions`.
+pub async fn start_attempt(
+    pool: &PgPool,
+    quiz_set: i32,
+    version: i32,
+    pass_mark: i32,
+    taker: i32,
+    questions: &[i32],
+    deadline: Option<DateTime<Utc>>,
+) -> Result<Attempt, Error> {
+    query_as!(
+        Attempt,
+        r#"
+insert into quiz_attempt (quiz_set, version, pass_mark, taker, questions, deadline)
+values ($1, $2, $3, $4, $5, $6)
+returning id, quiz_set, version, pass_mark, taker, questions, started, deadline, finished,
+    correct as "correct: Vec<bool>", score, passed, shared"#,
+        quiz_set,
+        version,
+        pass_mark,
+        taker,
+        questions,
+        deadline
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e: sqlx::Error| -> Error {
+        match PgError::try_from(e) {
+            Ok(pg) => match pg {
+                PgError::ForeignKeyViolation(_) => Error::NotFound,
+                _ => pg.into(),
+            },
+            Err(e) => e,
+        }
+    })
+}
+
+pub async fn get_attempt(pool: &PgPool, id: i64) -> Result<Attempt, Error> {
+    query_as!(
+        Attempt,
+        r#"
+select id, quiz_set, version, pass_mark, taker, questions, started, deadline, finished,
+    correct as "correct: Vec<bool>", score, passed, shared
+from quiz_attempt
+where id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Takers shown on the leaderboard of a quiz set.
+pub const LEADERBOARD_SIZE: i64 = 10;
+
+/// Record the answers of an unfinished attempt, given whether each was
+/// correct, and queue its delivery to the webhooks of the vtuber, along with
+/// the leaderboard if it changed while the quiz set is published. A `shared`
+/// result of a published quiz set is added to the feed.
+pub async fn finish_attempt(
+    pool: &PgPool,
+    id: i64,
+    correct: &[bool],
+    shared: bool,
+) -> Result<Attempt, Error> {
+    let score = correct.iter().filter(|c| **c).count() as i32;
+    let mut tx = pool.begin().await?;
+    // attempts of a quiz set finish one at a time, so that each change of its
+    // leaderboard is seen once
+    let quiz_set = query!(
+        r#"
+select s.id, s.vtuber, s.name, s.draft
+from quiz_set s
+join quiz_attempt a on a.quiz_set = s.id
+where a.id = $1
+for update of s"#,
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or(Error::NotFound)?;
+    let before = leaderboard_entries(&mut tx, quiz_set.id).await?;
+    let attempt = query_as!(
+        Attempt,
+        r#"
+with a as (
+    update quiz_attempt
+    set finished = current_timestamp, correct = $2, score = $3, passed = $3 >= pass_mark,
+        shared = $4
+    where id = $1 and finished is null
+    returning id, quiz_set, version, pass_mark, taker, questions, started, deadline,
+        finished, correct, score, passed, shared
+), d as (
+    insert into webhook_delivery (webhook, event, payload)
+    select w.id, 'attempt_finished', json_build_object(
+        'attempt', a.id, 'quiz_set', s.id, 'name', s.name, 'version', a.version,
+        'taker', a.taker, 'score', a.score, 'questions', cardinality(a.questions),
+        'passed', a.passed
+    )::text
+    from a
+    join quiz_set s on s.id = a.quiz_set
+    join webhook w on w.owner = s.vtuber
+)
+select id as "id!", quiz_set as "quiz_set!", version as "version!", pass_mark as "pass_mark!",
+    taker as "taker!",
+    questions as "questions!", started as "started!", deadline, finished,
+    correct as "correct: Vec<bool>", score, passed, shared as "shared!"
+from a"#,
+        id,
+        correct,
+        score,
+        shared
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or_else(|| Error::invalid("attempt", "has already been submitted"))?;
+    if shared && !quiz_set.draft {
+        query!(
+            r#"insert into event (kind, actor, attempt) values ('quiz_result', $1, $2)"#,
+            attempt.taker,
+            attempt.id
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    let after = leaderboard_entries(&mut tx, quiz_set.id).await?;
+    if !quiz_set.draft && after != before {
+        let payload = serde_json::json!({
+            "quiz_set": quiz_set.id,
+            "name": quiz_set.name,
+            "entries": after,
+        });
+        query!(
+            r#"
+insert into webhook_delivery (webhook, event, payload)
+select id, 'leaderboard_changed', $2 from webhook where owner = $1"#,
+            quiz_set.vtuber,
+            payload.to_string()
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(attempt)
+}
+
+/// The [`LEADERBOARD_SIZE`] best takers of a quiz set.
+pub async fn leaderboard(pool: &PgPool, quiz_set: i32) -> Result<Leaderboard, Error> {
+    let entries = leaderboard_entries(pool, quiz_set).await?;
+    Ok(Leaderboard { quiz_set, entries })
+}
+
+async fn leaderboard_entries<'e, E>(
+    executor: E,
+    quiz_set: i32,
+) -> Result<Vec<LeaderboardEntry>, Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+{
+    query_as!(
+        LeaderboardEntry,
+        r#"
+select b.taker as "taker!", u.username as "taker_name!", b.score as "score!",
+    b.finished as "finished!"
+from (
+    select distinct on (taker) taker, score, finished
+    from quiz_attempt
+    where quiz_set = $1 and finished is not null
+    order by taker, score desc, finished
+) b
+join "user" u on u.id = b.taker
+order by b.score desc, b.finished, b.taker
+limit $2"#,
+        quiz_set,
+        LEADERBOARD_SIZE
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn create_webhook(
+    pool: &PgPool,
+    owner: i32,
+    url: &str,
+    secret: &str,
+) -> Result<Webhook, Error> {
+    query_as!(
+        Webhook,
+        r#"
+insert into webhook (owner, url, secret) values ($1, $2, $3)
+returning id, url, created"#,
+        owner,
+        url,
+        secret
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e: sqlx::Error| -> Error {
+        match PgError::try_from(e) {
+            Ok(pg) => match pg {
+                PgError::UniqueViolation(_) => Error::invalid("url", "is already registered"),
+                PgError::ForeignKeyViolation(_) => Error::NotFound,
+                _ => pg.into(),
+            },
+            Err(e) => e,
+        }
+    })
+}
+
+pub async fn list_webhooks(pool: &PgPool, owner: i32) -> Result<Vec<Webhook>, Error> {
+    query_as!(
+        Webhook,
+        r#"select id, url, created from webhook where owner = $1 order by id"#,
+        owner
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Delete a webhook of `owner` and its deliveries.
+pub async fn delete_webhook(pool: &PgPool, owner: i32, id: i32) -> Result<(), Error> {
+    let result = query!(
+        r#"delete from webhook where id = $1 and owner = $2"#,
+        id,
+        owner
+    )
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+    Ok(())
+}
+
+/// Deliveries to a webhook of `owner` with ids below `before`, newest first.
+pub async fn list_deliveries(
+    pool: &PgPool,
+    owner: i32,
+    webhook: i32,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<DeliveryList, Error> {
+    query!(
+        r#"select id from webhook where id = $1 and owner = $2"#,
+        webhook,
+        owner
+    )
+    .fetch_one(pool)
+    .await?;
+    let rows = query!(
+        r#"
+select id, event as "event: WebhookEvent", payload, status as "status: DeliveryStatus",
+    attempts, last_status, last_error, created, delivered
+from webhook_delivery
+where webhook = $1 and ($2::bigint is null or id < $2)
+order by id desc
+limit $3"#,
+        webhook,
+        before,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    let deliveries: Vec<Delivery> = rows
+        .into_iter()
+        .map(|row| Delivery {
+            id: row.id,
+            event: row.event,
+            payload: serde_json::from_str(&row.payload).unwrap_or_default(),
+            status: row.status,
+            attempts: row.attempts,
+            last_status: row.last_status,
+            last_error: row.last_error,
+            created: row.created,
+            delivered: row.delivered,
+        })
+        .collect();
+    let next = match deliveries.last() {
+        Some(last) if deliveries.len() as i64 == limit => Some(last.id),
+        _ => None,
+    };
+    Ok(DeliveryList { deliveries, next })
+}
+
+/// A delivery due to be sent, with where to.
+#[derive(Clone, Debug)]
+pub struct DueDelivery {
+    pub id: i64,
+    pub event: WebhookEvent,
+    pub payload: String,
+    pub attempts: i32,
+    pub created: DateTime<Utc>,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Claim up to `limit` deliveries due to be sent, postponing their next
+/// attempt by `lease_secs` so that other instances skip them meanwhile.
+pub async fn claim_deliveries(
+    pool: &PgPool,
+    limit: i64,
+    lease_secs: f64,
+) -> Result<Vec<DueDelivery>, Error> {
+    query_as!(
+        DueDelivery,
+        r#"
+update webhook_delivery d
+set next_attempt = current_timestamp + make_interval(secs => $2)
+from webhook w
+where w.id = d.webhook
+    and d.id in (
+        select id from webhook_delivery
+        where status = 'pending' and next_attempt <= current_timestamp
+        order by next_attempt
+        limit $1
+        for update skip locked
+    )
+returning d.id, d.event as "event: WebhookEvent", d.payload, d.attempts, d.created, w.url, w.secret"#,
+        limit,
+        lease_secs
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Record an attempt of a delivery answered with a success `status`.
+pub async fn delivery_succeeded(pool: &PgPool, id: i64, status: i32) -> Result<(), Error> {
+    query!(
+        r#"
+update webhook_delivery
+set status = 'delivered', attempts = attempts + 1, last_status = $2, last_error = null,
+    delivered = current_timestamp
+where id = $1"#,
+        id,
+        status
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+/// Record a failed attempt of a delivery, to be retried after `retry_secs`,
+/// or given up if none.
+pub async fn delivery_failed(
+    pool: &PgPool,
+    id: i64,
+    status: Option<i32>,
+    error: &str,
+    retry_secs: Option<u64>,
+) -> Result<(), Error> {
+    query!(
+        r#"
+update webhook_delivery
+set status = case when $4::double precision is null
+        then 'failed'::delivery_status else 'pending'::delivery_status end,
+    attempts = attempts + 1, last_status = $2, last_error = $3,
+    next_attempt = current_timestamp + make_interval(secs => coalesce($4, 0))
+where id = $1"#,
+        id,
+        status,
+        error,
+        retry_secs.map(|secs| secs as f64)
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+pub fn hash_password(password: &[u8], params: &config::Argon2) -> Result<String, Error> {
     let mut rng = thread_rng();
     let mut salt = [0u8; 16];
     rng.fill_bytes(&mut salt);
-    let config = argon2::Config {
-        // ref: https://datatracker.ietf.org/doc/draft-irtf-cfrg-argon2/
-        variant: argon2::Variant::Argon2id,
-        ..Default::default()
-    };
-    argon2::hash_encoded(password, &salt, &config).unwrap()
+    Ok(argon2::hash_encoded(password, &salt, &params.config())?)
+}
+
+/// Fails if `encoded` is not a valid encoded hash.
+pub fn verify_password(encoded: &str, password: &[u8]) -> Result<bool, Error> {
+    Ok(argon2::verify_encoded(encoded, password)?)
 }
 
-fn verify_password(encoded: &str, password: &[u8]) -> bool {
-    argon2::verify_encoded(encoded, password).unwrap()
+/// Whether `encoded` was made with another variant or version, or with
+/// parameters weaker than `params` in any way.
+pub fn needs_rehash(encoded: &str, params: &config::Argon2) -> bool {
+    // $argon2id$v=19$m=4096,t=3,p=1$<salt>$<hash>
+    let parts: Vec<&str> = encoded.split('$').collect();
+    if parts.len() != 6 || parts[1] != "argon2id" || parts[2] != "v=19" {
+        return true;
+    }
+    let mut memory_cost = 0;
+    let mut time_cost = 0;
+    let mut parallelism = 0;
+    for param in parts[3].split(',') {
+        match param.split_once('=') {
+            Some(("m", v)) => memory_cost = v.parse().unwrap_or(0),
+            Some(("t", v)) => time_cost = v.parse().unwrap_or(0),
+            Some(("p", v)) => parallelism = v.parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+    memory_cost < params.memory_cost
+        || time_cost < params.time_cost
+        || parallelism < params.parallelism
 }
 
-fn generate_challenge_code() -> String {
+pub fn generate_challenge_code() -> String {
     const CHALLENGE_LEN: usize = 7;
     const CHALLENGE_CHARSET: &[u8] =
         b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
@@ -317,10 +1884,21 @@ impl From<Box<PgDatabaseError>> for PgError {
     fn from(e: Box<PgDatabaseError>) -> Self {
         use PgError::*;
 
+        let detail = Box::new(PgDetail {
+            code: e.code().to_string(),
+            message: e.message().to_string(),
+            detail: e.detail().map(str::to_string),
+            table: e.table().map(str::to_string),
+            column: e.column().map(str::to_string),
+            constraint: e.constraint().map(str::to_string),
+        });
         match e.code() {
-            "23505" => UniqueViolation,
-            "23514" => CheckViolation,
-            _ => Others,
+            "23505" => UniqueViolation(detail),
+            "23514" => CheckViolation(detail),
+            "23503" => ForeignKeyViolation(detail),
+            "23502" => NotNullViolation(detail),
+            "22001" => StringTruncation(detail),
+            _ => Others(detail),
         }
     }
 }
@@ -2,6 +2,7 @@ use actix_http::body::BoxBody;
 use actix_web::http::{header, StatusCode};
 use actix_web::{error, HttpResponse};
 use serde::Serialize;
+use serde_json::{json, Value};
 
 use Error::*;
 
@@ -12,46 +13,145 @@ pub enum Error {
     #[error(transparent)]
     Hcaptcha(#[from] crate::hcaptcha::HcaptchaError),
     #[error("database error")]
-    Pg(#[from] crate::db::PgError),
+    Pg(crate::db::PgError),
+    #[error("password hash error: {0}")]
+    PasswordHash(#[from] argon2::Error),
+    #[error("bilibili api error: {0}")]
+    Bilibili(#[from] crate::bilibili::Error),
     #[error("username has been taken")]
     ConflictUsername,
-    #[error("unauthorized")]
+    #[error("question has already been voted")]
+    ConflictVote,
+    #[error("bilibili account is bound to another user")]
+    ConflictBilibili,
+    #[error("invalid username or password")]
     InvalidCredential,
+    #[error("login required")]
+    Unauthenticated,
+    #[error("forbidden")]
+    Forbidden,
+    #[error("user has been blocked")]
+    Blocked,
+    #[error("too many failed login attempts")]
+    LoginThrottled { retry_after: u64 },
     #[error("not found")]
     NotFound,
+    #[error("invalid request")]
+    Validation(Vec<FieldError>),
+    #[error("invalid path parameter")]
+    BadPath(FieldError),
+    #[error("too many requests")]
+    RateLimited { retry_after: u64 },
+}
+
+/// Which part of a request was rejected, and why.
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
 pub struct ErrorDisplay {
     pub code: u64,
     pub err: String,
+    pub details: Value,
+    pub request_id: Option<String>,
 }
 
 impl Error {
+    /// Shorthand for a validation error on a single field.
+    pub fn invalid(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Validation(vec![FieldError::new(field, reason)])
+    }
+
     fn code(&self) -> u64 {
         match self {
             Sqlx(_) => 510000u64,
             Pg(_) => 511000u64,
+            PasswordHash(_) => 512000u64,
+            Bilibili(_) => 520000u64,
             Hcaptcha(_) => 410000u64,
             InvalidCredential => 420000u64,
+            Unauthenticated => 420003u64,
+            Forbidden => 420001u64,
+            Blocked => 420002u64,
+            LoginThrottled { .. } => 420004u64,
             ConflictUsername => 430001u64,
+            ConflictVote => 430002u64,
+            ConflictBilibili => 430003u64,
             NotFound => 440000u64,
+            Validation(_) => 450000u64,
+            BadPath(_) => 450001u64,
+            RateLimited { .. } => 460000u64,
         }
     }
 
+    /// Whether this is our fault rather than the client's.
+    pub fn is_internal(&self) -> bool {
+        matches!(self, Sqlx(_) | Pg(_) | PasswordHash(_) | Bilibili(_))
+    }
+
+    /// Message shown to clients. Internal errors and captcha failures are
+    /// only described in the logs.
     fn user_msg(&self) -> String {
         match self {
-            Sqlx(_) => "database error".to_string(),
-            Hcaptcha(e) => format!("{}", e),
+            Sqlx(_) | Pg(_) => "database error".to_string(),
+            PasswordHash(_) => "internal server error".to_string(),
+            Bilibili(_) => "bilibili is unavailable".to_string(),
+            Hcaptcha(_) => "captcha verification failed".to_string(),
             _ => format!("{}", self),
         }
     }
 
-    fn as_display(&self) -> ErrorDisplay {
+    fn details(&self) -> Value {
+        match self {
+            Validation(fields) => json!(fields),
+            BadPath(field) => json!([field]),
+            RateLimited { retry_after } | LoginThrottled { retry_after } => {
+                json!({ "retry_after": retry_after })
+            }
+            _ => Value::Null,
+        }
+    }
+
+    fn as_display(&self, request_id: Option<&str>) -> ErrorDisplay {
         ErrorDisplay {
             code: self.code(),
             err: self.user_msg(),
+            details: self.details(),
+            request_id: request_id.map(str::to_string),
+        }
+    }
+
+    /// Build the error response, tagged with the id of the request that
+    /// caused it.
+    pub fn response(&self, request_id: Option<&str>) -> HttpResponse {
+        let body = serde_json::to_string(&self.as_display(request_id)).unwrap_or_else(|e| {
+            error!("error occurred when generating error response: {}", e);
+            r#"{"code":500000, "err":"internal server error", "details":null, "request_id":null}"#
+                .to_string()
+        });
+        let mut resp =
+            HttpResponse::new(error::ResponseError::status_code(self)).set_body(BoxBody::new(body));
+        resp.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        if let RateLimited { retry_after } | LoginThrottled { retry_after } = self {
+            resp.headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from(*retry_after));
         }
+        resp
     }
 }
 
@@ -60,25 +160,28 @@ impl error::ResponseError for Error {
         match self {
             Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Pg(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PasswordHash(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Bilibili(_) => StatusCode::BAD_GATEWAY,
             Hcaptcha(_) => StatusCode::FORBIDDEN,
-            InvalidCredential => StatusCode::FORBIDDEN,
+            InvalidCredential => StatusCode::UNAUTHORIZED,
+            Unauthenticated => StatusCode::UNAUTHORIZED,
+            Forbidden => StatusCode::FORBIDDEN,
+            Blocked => StatusCode::FORBIDDEN,
+            LoginThrottled { .. } => StatusCode::TOO_MANY_REQUESTS,
             ConflictUsername => StatusCode::CONFLICT,
+            ConflictVote => StatusCode::CONFLICT,
+            ConflictBilibili => StatusCode::CONFLICT,
             NotFound => StatusCode::NOT_FOUND,
+            Validation(_) => StatusCode::BAD_REQUEST,
+            BadPath(_) => StatusCode::BAD_REQUEST,
+            RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
+    /// Logging is left to `RequestIdMiddleware`, which knows the request id
+    /// and replaces this response with one carrying it.
     fn error_response(&self) -> HttpResponse {
-        error!("{}", self);
-        let body = serde_json::to_string(&self.as_display()).unwrap_or_else(|e| {
-            error!("error occurred when generating error response: {}", e);
-            r#"{"code":500000, "err":"internal server error"}"#.to_string()
-        });
-        let mut resp = HttpResponse::new(self.status_code()).set_body(BoxBody::new(body));
-        resp.headers_mut().insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static("application/json"),
-        );
-        resp
+        self.response(None)
     }
 }
 
@@ -91,3 +194,46 @@ impl From<sqlx::Error> for Error {
         }
     }
 }
+
+impl From<crate::db::PgError> for Error {
+    fn from(e: crate::db::PgError) -> Self {
+        use crate::db::PgError::*;
+        match &e {
+            NotNullViolation(_) => match e.column() {
+                Some(column) => Self::invalid(column, "is required"),
+                None => Self::Pg(e),
+            },
+            _ => Self::Pg(e),
+        }
+    }
+}
+
+/// Error handler for the `Json` extractor, so malformed bodies get a
+/// structured response instead of actix's plain text one.
+pub fn json_error_handler(
+    err: actix_web::error::JsonPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    Error::invalid("body", err.to_string()).into()
+}
+
+/// Error handler for the `Path` extractor.
+pub fn path_error_handler(
+    err: actix_web::error::PathError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    BadPath(FieldError::new("path", err.to_string())).into()
+}
+
+/// Error handler for the `Query` extractor.
+pub fn query_error_handler(
+    err: actix_web::error::QueryPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    Error::invalid("query", err.to_string()).into()
+}
+
+/// Fallback for unmatched routes under the api scope.
+pub async fn not_found() -> Result<HttpResponse, Error> {
+    Err(NotFound)
+}
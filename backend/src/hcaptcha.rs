@@ -2,13 +2,22 @@ use std::future::Future;
 use std::net::IpAddr;
 use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::Arc;
 
+use actix_http::HttpMessage;
+use actix_session::SessionExt;
 use actix_web::dev::Payload;
 use actix_web::web::Data;
 use actix_web::{FromRequest, HttpRequest};
+use async_trait::async_trait;
 use paperclip::actix::Apiv2Security;
+use serde::Deserialize;
 
+use crate::config;
 use crate::error::Error;
+use crate::request_id::RequestId;
+use crate::risk::Signals;
+use crate::storage::SharedStorage;
 
 #[derive(Apiv2Security)]
 #[openapi(
@@ -31,6 +40,132 @@ pub enum HcaptchaError {
     Invalid,
 }
 
+/// Checks the captcha response sent by a client.
+#[async_trait]
+pub trait CaptchaVerifier: Send + Sync {
+    /// Verify `response`, the `X-HCAPTCHA-KEY` header if sent, solved by the
+    /// client at `ip`.
+    async fn verify(&self, response: Option<&str>, ip: Option<IpAddr>)
+        -> Result<(), HcaptchaError>;
+}
+
+pub type SharedCaptcha = Arc<dyn CaptchaVerifier>;
+
+/// Verifies responses with the hCaptcha service.
+pub struct HcaptchaVerifier {
+    pub secret: String,
+    pub site_key: String,
+}
+
+#[async_trait]
+impl CaptchaVerifier for HcaptchaVerifier {
+    async fn verify(
+        &self,
+        response: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Result<(), HcaptchaError> {
+        let response = response.ok_or(HcaptchaError::Missing)?;
+        let ip = ip.ok_or(HcaptchaError::InsufficientInformation)?;
+        hcaptcha::Hcaptcha::new(self.secret.as_str(), response)
+            .set_site_key(self.site_key.as_str())
+            .set_user_ip(&ip)
+            .verify()
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                error!("Hcaptcha failed: {:?}", e);
+                HcaptchaError::Invalid
+            })
+    }
+}
+
+/// Accepts every request, with or without a response. For tests and local
+/// development only.
+pub struct AlwaysPass;
+
+#[async_trait]
+impl CaptchaVerifier for AlwaysPass {
+    async fn verify(&self, _: Option<&str>, _: Option<IpAddr>) -> Result<(), HcaptchaError> {
+        Ok(())
+    }
+}
+
+/// Rejects every request, to test how clients handle captcha failures.
+pub struct AlwaysFail;
+
+#[async_trait]
+impl CaptchaVerifier for AlwaysFail {
+    async fn verify(&self, response: Option<&str>, _: Option<IpAddr>) -> Result<(), HcaptchaError> {
+        response.ok_or(HcaptchaError::Missing)?;
+        Err(HcaptchaError::Invalid)
+    }
+}
+
+/// Posts responses to `url`, which speaks the hCaptcha `siteverify` protocol,
+/// e.g. a stub service in staging.
+pub struct HttpVerifier {
+    pub url: String,
+    pub secret: Option<String>,
+    pub site_key: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct SiteVerify {
+    success: bool,
+}
+
+impl HttpVerifier {
+    pub fn new(url: String, secret: Option<String>, site_key: String) -> Self {
+        Self {
+            url,
+            secret,
+            site_key,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl CaptchaVerifier for HttpVerifier {
+    async fn verify(
+        &self,
+        response: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Result<(), HcaptchaError> {
+        let response = response.ok_or(HcaptchaError::Missing)?;
+        let mut form = vec![
+            ("response", response.to_string()),
+            ("sitekey", self.site_key.clone()),
+        ];
+        if let Some(secret) = &self.secret {
+            form.push(("secret", secret.clone()));
+        }
+        if let Some(ip) = ip {
+            form.push(("remoteip", ip.to_string()));
+        }
+        let result = async {
+            self.client
+                .post(self.url.as_str())
+                .form(&form)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<SiteVerify>()
+                .await
+        }
+        .await;
+        match result {
+            Ok(SiteVerify { success: true }) => Ok(()),
+            Ok(_) => Err(HcaptchaError::Invalid),
+            Err(e) => {
+                error!("captcha verification at {} failed: {:?}", self.url, e);
+                Err(HcaptchaError::Invalid)
+            }
+        }
+    }
+}
+
 impl FromRequest for Hcaptcha {
     type Error = Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
@@ -39,29 +174,55 @@ impl FromRequest for Hcaptcha {
         if cfg!(feature = "bypass-hcaptcha") {
             return Box::pin(async { Ok(Hcaptcha { _private: () }) });
         }
-        let response: Option<&str> = req
+        let response = req
             .headers()
             .get("X-HCAPTCHA-KEY")
-            .and_then(|key| key.to_str().ok());
-        if response.is_none() {
-            return Box::pin(async { Err(HcaptchaError::Missing.into()) });
-        }
-        let user_ip = IpAddr::from_str(req.connection_info().realip_remote_addr().unwrap());
-        if user_ip.is_err() {
-            return Box::pin(async { Err(HcaptchaError::InsufficientInformation.into()) });
-        }
-        let config = req.app_data::<Data<crate::config::HCaptcha>>().unwrap();
-        let mut hc = hcaptcha::Hcaptcha::new(config.secret.as_str(), response.unwrap())
-            .set_site_key(config.site_key.as_str())
-            .set_user_ip(&user_ip.unwrap());
+            .and_then(|key| key.to_str().ok())
+            .map(str::to_string);
+        let addr = match req.app_data::<Data<config::Login>>() {
+            Some(login) => login.client_addr(req.head()),
+            None => config::Login::default().client_addr(req.head()),
+        };
+        let user_ip = IpAddr::from_str(&addr).ok();
+        let verifier = req
+            .app_data::<Data<SharedCaptcha>>()
+            .unwrap()
+            .get_ref()
+            .clone();
+        let risk = req.app_data::<Data<config::Risk>>().cloned();
+        let storage = req.app_data::<Data<SharedStorage>>().cloned();
+        let user = req.get_session().get::<i32>("user").ok().flatten();
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.0.clone())
+            .unwrap_or_default();
+        let route = format!("{} {}", req.method(), req.path());
         Box::pin(async move {
-            hc.verify()
+            if let (Some(risk), Some(storage)) = (risk, storage) {
+                if risk.enabled {
+                    let signals = Signals::gather(&storage, &risk, user, &addr).await?;
+                    let score = signals.score(&risk);
+                    let challenge = score >= risk.threshold;
+                    info!(
+                        "[{}] captcha risk {} for {} from {}: {} {:?}",
+                        request_id,
+                        score,
+                        route,
+                        addr,
+                        if challenge { "challenge" } else { "skip" },
+                        signals
+                    );
+                    if !challenge {
+                        return Ok(Hcaptcha { _private: () });
+                    }
+                }
+            }
+            verifier
+                .verify(response.as_deref(), user_ip)
                 .await
                 .map(|_| Hcaptcha { _private: () })
-                .map_err(|e| {
-                    error!("Hcaptcha failed: {:?}", e);
-                    HcaptchaError::Invalid.into()
-                })
+                .map_err(|e| e.into())
         })
     }
 }
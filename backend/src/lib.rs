@@ -0,0 +1,19 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate sqlx;
+
+pub mod bilibili;
+pub mod cli;
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod hcaptcha;
+pub mod live;
+pub mod migrate;
+pub mod rate_limit;
+pub mod request_id;
+pub mod risk;
+pub mod services;
+pub mod storage;
+pub mod webhook;
@@ -0,0 +1,178 @@
+//! Live updates pushed to clients as server-sent events.
+//!
+//! Triggers announce every row inserted into `notification` and `event` with
+//! `pg_notify`, so each backend instance hears of them whichever instance made
+//! the write. [`listen`] loads the rows and hands them to the [`Broker`] of its
+//! instance, which fans them out to the streams of connected clients.
+
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream};
+use serde_json::json;
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{timeout_at, Instant};
+use vtuber_quiz_commons::models::{FeedEvent, Notification};
+
+use crate::config;
+use crate::db;
+use crate::error::Error;
+
+/// Channels notified by the triggers, named after their tables.
+const CHANNELS: [&str; 2] = ["notification", "event"];
+
+/// Wait before listening again when the connection is lost.
+const RETRY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub enum LiveMessage {
+    Notification {
+        recipient: i32,
+        notification: Notification,
+    },
+    /// an event of the feed
+    Event(FeedEvent),
+}
+
+impl LiveMessage {
+    /// Whether the stream of `user`, who follows `followees`, gets the message.
+    fn is_for(&self, user: i32, followees: &HashSet<i32>) -> bool {
+        match self {
+            LiveMessage::Notification { recipient, .. } => *recipient == user,
+            LiveMessage::Event(event) => {
+                followees.contains(&event.actor)
+                    || event.vtuber.is_some_and(|id| followees.contains(&id))
+            }
+        }
+    }
+
+    fn to_sse(&self) -> Bytes {
+        let (name, data) = match self {
+            LiveMessage::Notification { notification, .. } => {
+                ("notification", serde_json::to_string(notification))
+            }
+            LiveMessage::Event(event) => ("event", serde_json::to_string(event)),
+        };
+        sse(name, &data.expect("models serialize to JSON"))
+    }
+}
+
+fn sse(name: &str, data: &str) -> Bytes {
+    Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+}
+
+/// Fans messages out to the streams of one backend instance.
+#[derive(Clone)]
+pub struct Broker {
+    sender: broadcast::Sender<LiveMessage>,
+    keep_alive: Duration,
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        Self::new(&config::Live::default())
+    }
+}
+
+impl Broker {
+    pub fn new(live: &config::Live) -> Self {
+        let (sender, _) = broadcast::channel(live.buffer);
+        Self {
+            sender,
+            keep_alive: Duration::from_secs(live.keep_alive_secs),
+        }
+    }
+
+    pub fn publish(&self, message: LiveMessage) {
+        // fails only when no stream is connected
+        let _ = self.sender.send(message);
+    }
+
+    /// Server-sent events of the messages for `user`, who follows
+    /// `followees`, from now on.
+    ///
+    /// A stream lagging behind by more than the buffer skips the older
+    /// messages and gets a `lagged` event, telling the client to reload its
+    /// lists. Idle streams get a comment every `keep_alive`.
+    pub fn stream(
+        &self,
+        user: i32,
+        followees: HashSet<i32>,
+    ) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        let receiver = self.sender.subscribe();
+        let keep_alive = self.keep_alive;
+        let followees = Arc::new(followees);
+        stream::unfold(receiver, move |mut receiver| {
+            let followees = followees.clone();
+            async move {
+                let deadline = Instant::now() + keep_alive;
+                loop {
+                    let bytes = match timeout_at(deadline, receiver.recv()).await {
+                        Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+                        Ok(Ok(message)) if message.is_for(user, &followees) => message.to_sse(),
+                        Ok(Ok(_)) => continue,
+                        Ok(Err(RecvError::Lagged(skipped))) => {
+                            sse("lagged", &json!({ "skipped": skipped }).to_string())
+                        }
+                        Ok(Err(RecvError::Closed)) => return None,
+                    };
+                    return Some((Ok(bytes), receiver));
+                }
+            }
+        })
+    }
+}
+
+/// Publish the notifications and events announced by Postgres to `broker`,
+/// until the pool is closed.
+pub async fn listen(pool: PgPool, broker: Broker) {
+    while !pool.is_closed() {
+        if let Err(e) = forward(&pool, &broker).await {
+            error!("live updates interrupted: {:?}", e);
+            tokio::time::sleep(RETRY).await;
+        }
+    }
+}
+
+async fn forward(pool: &PgPool, broker: &Broker) -> Result<(), Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen_all(CHANNELS).await?;
+    loop {
+        let notification = listener.recv().await?;
+        let id = match notification.payload().parse() {
+            Ok(id) => id,
+            Err(_) => {
+                warn!(
+                    "unexpected payload on {}: {}",
+                    notification.channel(),
+                    notification.payload()
+                );
+                continue;
+            }
+        };
+        match load(pool, notification.channel(), id).await {
+            Ok(Some(message)) => broker.publish(message),
+            Ok(None) => {}
+            Err(e) => error!("failed to load {} {}: {:?}", notification.channel(), id, e),
+        }
+    }
+}
+
+async fn load(pool: &PgPool, channel: &str, id: i64) -> Result<Option<LiveMessage>, Error> {
+    Ok(match channel {
+        "notification" => {
+            let (recipient, notification) = db::get_notification(pool, id).await?;
+            Some(LiveMessage::Notification {
+                recipient,
+                notification,
+            })
+        }
+        "event" => Some(LiveMessage::Event(db::get_event(pool, id).await?)),
+        _ => None,
+    })
+}
@@ -1,62 +1,116 @@
 #[macro_use]
 extern crate log;
-#[macro_use]
-extern crate sqlx;
-
-use std::borrow::Borrow;
 
 use actix_web::middleware::Logger;
 use actix_web::web::Data;
 use actix_web::{App, HttpServer};
-use paperclip::actix::{web, OpenApiExt};
-use sqlx::postgres::PgPoolOptions;
+use clap::Parser;
+use paperclip::actix::OpenApiExt;
+use sqlx::PgPool;
+use std::sync::Arc;
 
-use crate::config::Config;
+use vtuber_quiz_backend::bilibili::{BilibiliApi, SharedBilibili};
+use vtuber_quiz_backend::cli::{self, Cli, Command};
+use vtuber_quiz_backend::config::{Config, RateLimitStore};
+use vtuber_quiz_backend::live::{self, Broker};
+use vtuber_quiz_backend::rate_limit::{RateLimiter, SharedRateLimitStore};
+use vtuber_quiz_backend::request_id::RequestIdMiddleware;
+use vtuber_quiz_backend::storage::{MemoryStorage, PgStorage, SharedStorage};
+use vtuber_quiz_backend::{migrate, services, webhook};
 
-mod bilibili;
-mod config;
-mod db;
-mod error;
-mod hcaptcha;
-mod services;
+/// actix's default format, followed by the request id.
+const LOG_FORMAT: &str = r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#;
 
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
     pretty_env_logger::init();
-    let config = Config::default();
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            let (config, pool) = connect().await?;
+            serve(config, pool).await
+        }
+        Command::Migrate { action } => {
+            let (_, pool) = connect().await?;
+            cli::migrate(&pool, action).await
+        }
+        Command::User { action } => {
+            let (config, pool) = connect().await?;
+            cli::user(&pool, &config.argon2, action).await
+        }
+        Command::Question { action } => {
+            let (_, pool) = connect().await?;
+            cli::question(&pool, action).await
+        }
+        Command::RecomputeReputation { username } => {
+            let (_, pool) = connect().await?;
+            cli::recompute_reputation(&pool, username).await
+        }
+        Command::Export { output } => {
+            let (_, pool) = connect().await?;
+            cli::export(&pool, output).await
+        }
+        // needs neither the config nor the database
+        Command::WebhookReceiver { bind, secret, fail } => {
+            cli::webhook_receiver(bind, secret, fail).await
+        }
+    }
+}
 
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect_with(config.database.borrow().into())
+/// Load the config and connect to its database.
+async fn connect() -> anyhow::Result<(Config, PgPool)> {
+    let config = Config::load()?;
+    let pool = config
+        .database
+        .pool_options()
+        .connect_with(config.database.connect_options()?)
         .await?;
+    Ok((config, pool))
+}
+
+async fn serve(config: Config, pool: PgPool) -> anyhow::Result<()> {
+    if config.database.migrate {
+        info!("running database migrations");
+        migrate::run(&pool).await?;
+    }
 
+    let rate_limit = &config.rate_limit;
+    let rate_limiter = if rate_limit.enabled {
+        let store: SharedRateLimitStore = match rate_limit.store {
+            RateLimitStore::Memory => Arc::new(MemoryStorage::new()),
+            RateLimitStore::Postgres => Arc::new(PgStorage::new(pool.clone())),
+        };
+        RateLimiter::new(&rate_limit.rules, store)
+    } else {
+        RateLimiter::default()
+    };
+    let broker = Broker::new(&config.live);
+    actix_web::rt::spawn(live::listen(pool.clone(), broker.clone()));
+    if config.webhook.enabled {
+        actix_web::rt::spawn(webhook::run(pool.clone(), config.webhook.clone()));
+    }
+    let storage: SharedStorage = Arc::new(PgStorage::new(pool).with_argon2(config.argon2.clone()));
+    let bilibili: SharedBilibili = Arc::new(BilibiliApi);
+    let captcha = config.hcaptcha.verifier();
     let config_cloned = config.clone();
     HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
+            .wrap(rate_limiter.clone())
+            .wrap(RequestIdMiddleware)
+            .wrap(Logger::new(LOG_FORMAT))
             .wrap(config_cloned.host.cookie.session_middleware())
-            .app_data(Data::new(config_cloned.hcaptcha.clone()))
-            .app_data(Data::new(pool.clone()))
+            .app_data(Data::new(captcha.clone()))
+            .app_data(Data::new(config_cloned.account.clone()))
+            .app_data(Data::new(config_cloned.login.clone()))
+            .app_data(Data::new(config_cloned.risk.clone()))
+            .app_data(Data::new(config_cloned.webhook.clone()))
+            .app_data(Data::new(storage.clone()))
+            .app_data(Data::new(bilibili.clone()))
+            .app_data(Data::new(broker.clone()))
             .wrap_api()
             .with_json_spec_at("/api/spec/v2")
             .with_swagger_ui_at("/api/docs")
-            .service(
-                web::scope("/api")
-                    .service(services::register)
-                    .service(services::login)
-                    .service(services::get_self)
-                    .service(services::follow_user)
-                    .service(services::unfollow_user)
-                    .service(services::create_challenge_code)
-                    .service(services::create_question)
-                    .service(services::get_question)
-                    .service(services::delete_question)
-                    .service(services::update_question)
-                    .service(services::apply_question_to_vtuber)
-                    .service(services::remove_question_to_vtuber)
-                    .service(services::get_question_applied)
-                    .service(services::vote_to_question),
-            )
+            .configure(services::routes)
             .build()
     })
     .bind(&config.host.bind)?
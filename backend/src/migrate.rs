@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
+use sqlx::PgPool;
+
+/// Migrations under `migrations/`, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum State {
+    Applied,
+    Pending,
+    /// applied, but the embedded migration has been modified since
+    ChecksumMismatch,
+}
+
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub version: i64,
+    pub description: String,
+    pub state: State,
+}
+
+/// Apply all pending migrations.
+pub async fn run(pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Compare embedded migrations with those recorded in the database.
+///
+/// Returns the status of each embedded migration, and the version of a
+/// partially applied migration if there is one.
+pub async fn status(pool: &PgPool) -> Result<(Vec<Status>, Option<i64>), MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let dirty = conn.dirty_version().await?;
+    let applied: HashMap<_, _> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum))
+        .collect();
+    let status = migrations()
+        .map(|m| Status {
+            version: m.version,
+            description: m.description.to_string(),
+            state: match applied.get(&m.version) {
+                None => State::Pending,
+                Some(checksum) if *checksum != m.checksum => State::ChecksumMismatch,
+                Some(_) => State::Applied,
+            },
+        })
+        .collect();
+    Ok((status, dirty))
+}
+
+/// Migrations that `run` would apply, without applying them.
+pub async fn pending(pool: &PgPool) -> Result<Vec<&'static Migration>, MigrateError> {
+    let (status, _) = status(pool).await?;
+    Ok(migrations()
+        .filter(|m| {
+            status
+                .iter()
+                .any(|s| s.version == m.version && s.state == State::Pending)
+        })
+        .collect())
+}
+
+fn migrations() -> impl Iterator<Item = &'static Migration> {
+    MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+}
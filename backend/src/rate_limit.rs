@@ -0,0 +1,247 @@
+//! Per-route rate limiting.
+//!
+//! Each configured route has a token bucket per client: the logged in user,
+//! or the client address for anonymous requests, as resolved by
+//! [`config::Login::client_addr`]. A request takes a token from
+//! its bucket, or is answered with `Error::RateLimited` when it is empty.
+//! Buckets live in a [`RateLimitRepository`], either in process memory or in
+//! Postgres to share them between backend instances.
+
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_http::body::{EitherBody, MessageBody};
+use actix_http::Method;
+use actix_session::SessionExt;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::web::Data;
+
+use crate::config;
+use crate::error::Error;
+use crate::storage::{MemoryStorage, RateLimitRepository};
+
+pub type SharedRateLimitStore = Arc<dyn RateLimitRepository + Send + Sync>;
+
+/// A token bucket holding up to `capacity` tokens, refilled by one token every
+/// `refill_secs` seconds.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Bucket {
+    pub capacity: u32,
+    pub refill_secs: u64,
+}
+
+impl Bucket {
+    /// Take a token from a bucket that held `tokens` tokens `elapsed` seconds
+    /// ago. Returns the tokens left, and the seconds to wait for a token if
+    /// none could be taken.
+    pub fn take(&self, tokens: f64, elapsed: f64) -> (f64, Option<u64>) {
+        let tokens =
+            (tokens + elapsed.max(0.0) / self.refill_secs as f64).min(self.capacity as f64);
+        if tokens >= 1.0 {
+            (tokens - 1.0, None)
+        } else {
+            let wait = ((1.0 - tokens) * self.refill_secs as f64).ceil() as u64;
+            (tokens, Some(wait.max(1)))
+        }
+    }
+}
+
+/// A route such as `POST /api/user/vote/{qid}/{action}`, where `{...}`
+/// matches any single path segment.
+#[derive(Clone, Debug)]
+struct Rule {
+    route: String,
+    method: Method,
+    segments: Vec<String>,
+    bucket: Bucket,
+}
+
+impl Rule {
+    fn new(rule: &config::RateLimitRule) -> Result<Self, String> {
+        let (method, path) = rule
+            .route
+            .split_once(' ')
+            .ok_or("expected a method and a path, e.g. `POST /api/question`")?;
+        let method = Method::from_str(method).map_err(|_| format!("invalid method {}", method))?;
+        if !path.starts_with('/') {
+            return Err(format!("path {} does not start with `/`", path));
+        }
+        Ok(Self {
+            route: rule.route.clone(),
+            method,
+            segments: path.split('/').map(str::to_string).collect(),
+            bucket: Bucket {
+                capacity: rule.capacity,
+                refill_secs: rule.refill_secs,
+            },
+        })
+    }
+
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        *method == self.method
+            && path.split('/').count() == self.segments.len()
+            && path.split('/').zip(&self.segments).all(|(s, pattern)| {
+                s == pattern
+                    || (pattern.starts_with('{') && pattern.ends_with('}') && !s.is_empty())
+            })
+    }
+}
+
+/// Check a configured rule, returning why it is invalid.
+pub fn check_rule(rule: &config::RateLimitRule) -> Result<(), String> {
+    Rule::new(rule).map(|_| ())
+}
+
+/// Middleware enforcing per-route quotas. It must be wrapped by the session
+/// middleware, to tell logged in users apart.
+#[derive(Clone)]
+pub struct RateLimiter {
+    rules: Arc<Vec<Rule>>,
+    store: SharedRateLimitStore,
+}
+
+impl Default for RateLimiter {
+    /// A limiter without any rule.
+    fn default() -> Self {
+        Self {
+            rules: Arc::new(Vec::new()),
+            store: Arc::new(MemoryStorage::new()),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Build the limiter for `rules`, which must have passed [`check_rule`].
+    pub fn new(rules: &[config::RateLimitRule], store: SharedRateLimitStore) -> Self {
+        let rules = rules
+            .iter()
+            .map(|rule| Rule::new(rule).expect("rate limit rules are validated with the config"))
+            .collect();
+        Self {
+            rules: Arc::new(rules),
+            store,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimiterService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterService {
+            service: Rc::new(service),
+            limiter: self.clone(),
+        }))
+    }
+}
+
+pub struct RateLimiterService<S> {
+    service: Rc<S>,
+    limiter: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let rule = self
+            .limiter
+            .rules
+            .iter()
+            .find(|rule| rule.matches(req.method(), req.path()))
+            .cloned();
+        let store = self.limiter.store.clone();
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            if let Some(rule) = rule {
+                let client = match req.get_session().get::<i32>("user").ok().flatten() {
+                    Some(id) => format!("user:{}", id),
+                    None => {
+                        let addr = match req.app_data::<Data<config::Login>>() {
+                            Some(login) => login.client_addr(req.head()),
+                            None => config::Login::default().client_addr(req.head()),
+                        };
+                        format!("ip:{}", addr)
+                    }
+                };
+                let key = format!("{} {}", rule.route, client);
+                let err = match store.take_token(&key, rule.bucket).await {
+                    Ok(None) => None,
+                    Ok(Some(retry_after)) => Some(Error::RateLimited { retry_after }),
+                    Err(e) => Some(e),
+                };
+                if let Some(e) = err {
+                    return Ok(req.error_response(e).map_into_right_body());
+                }
+            }
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        let bucket = Bucket {
+            capacity: 2,
+            refill_secs: 10,
+        };
+        assert_eq!(bucket.take(2.0, 0.0), (1.0, None));
+        assert_eq!(bucket.take(0.0, 15.0), (0.5, None));
+        assert_eq!(bucket.take(0.5, 0.0), (0.5, Some(5)));
+        // never more than the capacity
+        assert_eq!(bucket.take(1.0, 1000.0), (1.0, None));
+    }
+
+    #[test]
+    fn test_rule() {
+        let rule = Rule::new(&config::RateLimitRule {
+            route: "POST /api/user/vote/{qid}/{action}".to_string(),
+            capacity: 1,
+            refill_secs: 1,
+        })
+        .unwrap();
+        assert!(rule.matches(&Method::POST, "/api/user/vote/1/up"));
+        assert!(rule.matches(&Method::POST, "/api/user/vote/1/up/"));
+        assert!(!rule.matches(&Method::GET, "/api/user/vote/1/up"));
+        assert!(!rule.matches(&Method::POST, "/api/user/vote/1"));
+        assert!(!rule.matches(&Method::POST, "/api/user/vote//up"));
+
+        for route in ["/api/question", "POST api/question", "P@ST /api/question"] {
+            let rule = config::RateLimitRule {
+                route: route.to_string(),
+                capacity: 1,
+                refill_secs: 1,
+            };
+            assert!(check_rule(&rule).is_err(), "{}", route);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Request correlation ids.
+//!
+//! Every request gets an id, taken from the `X-Request-Id` header when the
+//! client (or a proxy in front of us) sent a sane one, generated otherwise.
+//! The id is echoed in the response header and in the body of error
+//! responses, and prefixed to the log line of every error, so a user report
+//! can be matched with the full server-side error.
+
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_http::body::{EitherBody, MessageBody};
+use actix_http::HttpMessage;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use rand::{thread_rng, Rng};
+
+use crate::error::Error;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Id of the current request, stored in the request extensions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(format!("{:016x}", thread_rng().gen::<u64>()))
+    }
+
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= 64
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.');
+        valid.then(|| Self(value.to_string()))
+    }
+}
+
+/// Middleware assigning a `RequestId` to every request.
+#[derive(Clone, Debug, Default)]
+pub struct RequestIdMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequestIdService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(RequestId::from_header)
+            .unwrap_or_else(RequestId::generate);
+        req.extensions_mut().insert(id.clone());
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let res = service.call(req).await?.map_into_left_body();
+            let replacement = res
+                .response()
+                .error()
+                .and_then(|e| match e.as_error::<Error>() {
+                    Some(e) => {
+                        if e.is_internal() {
+                            error!("[{}] {:?}", id.0, e);
+                        } else {
+                            info!("[{}] {}", id.0, e);
+                        }
+                        Some(e.response(Some(id.0.as_str())))
+                    }
+                    None => {
+                        if res.status().is_server_error() {
+                            error!("[{}] {:?}", id.0, e);
+                        }
+                        None
+                    }
+                });
+            let mut res = match replacement {
+                Some(resp) => res.into_response(resp).map_into_right_body(),
+                None => res,
+            };
+            if let Ok(value) = HeaderValue::from_str(id.0.as_str()) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        })
+    }
+}
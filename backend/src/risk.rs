@@ -0,0 +1,117 @@
+//! Risk scoring for captcha protected endpoints.
+//!
+//! Instead of challenging every request, the `Hcaptcha` extractor scores what
+//! is known of the client and only demands a captcha when the score reaches
+//! `risk.threshold`. Every decision is logged along with its signals, so the
+//! weights in `[risk]` can be tuned from the logs.
+
+use chrono::Utc;
+
+use crate::config;
+use crate::error::Error;
+use crate::rate_limit::Bucket;
+use crate::storage::SharedStorage;
+
+/// What is known of a request when deciding whether to challenge it.
+#[derive(Clone, Debug, Default)]
+pub struct Signals {
+    /// age in days and reputation of the logged in user
+    pub account: Option<(i64, i32)>,
+    /// recent failed logins from the client address
+    pub login_failures: u32,
+    /// whether the client sent more captcha protected requests than allowed
+    /// by the burst bucket
+    pub burst: bool,
+}
+
+impl Signals {
+    /// Gather the signals of a request by `user`, if logged in, from `addr`.
+    pub async fn gather(
+        storage: &SharedStorage,
+        risk: &config::Risk,
+        user: Option<i32>,
+        addr: &str,
+    ) -> Result<Self, Error> {
+        let account = match user {
+            Some(id) => {
+                let user = storage.get_user_by_id(id).await?;
+                Some(((Utc::now() - user.created).num_days(), user.reputation))
+            }
+            None => None,
+        };
+        let login_failures = storage
+            .login_failures(&format!("ip:{}", addr), risk.failure_window_secs)
+            .await?;
+        let client = match user {
+            Some(id) => format!("user:{}", id),
+            None => format!("ip:{}", addr),
+        };
+        let bucket = Bucket {
+            capacity: risk.burst_capacity,
+            refill_secs: risk.burst_refill_secs,
+        };
+        let burst = storage
+            .take_token(&format!("captcha {}", client), bucket)
+            .await?
+            .is_some();
+        Ok(Self {
+            account,
+            login_failures,
+            burst,
+        })
+    }
+
+    pub fn score(&self, risk: &config::Risk) -> u32 {
+        let mut score = match self.account {
+            None => risk.anonymous,
+            Some((age_days, reputation)) => {
+                let mut score = 0;
+                if age_days < risk.new_account_days as i64 {
+                    score += risk.new_account;
+                }
+                if reputation < risk.min_reputation {
+                    score += risk.low_reputation;
+                }
+                score
+            }
+        };
+        score = score.saturating_add(risk.login_failure.saturating_mul(self.login_failures));
+        if self.burst {
+            score = score.saturating_add(risk.burst);
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        let risk = config::Risk::default();
+        let trusted = Signals {
+            account: Some((30, 100)),
+            ..Default::default()
+        };
+        assert_eq!(trusted.score(&risk), 0);
+        assert_eq!(Signals::default().score(&risk), risk.anonymous);
+
+        let new_user = Signals {
+            account: Some((1, 100)),
+            ..Default::default()
+        };
+        assert_eq!(new_user.score(&risk), risk.new_account);
+        let suspicious = Signals {
+            account: Some((30, 0)),
+            login_failures: 1,
+            ..Default::default()
+        };
+        assert_eq!(suspicious.score(&risk), 50);
+        let burst = Signals {
+            burst: true,
+            ..trusted
+        };
+        assert!(burst.score(&risk) >= risk.threshold);
+    }
+}
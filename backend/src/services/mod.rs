@@ -1,5 +1,117 @@
+use actix_session::Session;
+use paperclip::actix::web;
+use vtuber_quiz_commons::models::PageRequest;
+
+use crate::error::{self, Error, FieldError};
+
 mod question;
+mod quiz;
 mod user;
 
 pub use question::*;
+pub use quiz::*;
 pub use user::*;
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api")
+            .app_data(web::JsonConfig::default().error_handler(error::json_error_handler))
+            .app_data(web::PathConfig::default().error_handler(error::path_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(error::query_error_handler))
+            .service(register)
+            .service(login)
+            .service(get_self)
+            .service(get_feed)
+            .service(list_notifications)
+            .service(stream_live)
+            .service(count_unread_notifications)
+            .service(mark_notifications_read)
+            .service(create_webhook)
+            .service(list_webhooks)
+            .service(delete_webhook)
+            .service(list_deliveries)
+            .service(get_user_by_id)
+            .service(get_user_by_username)
+            .service(change_password)
+            .service(follow_user)
+            .service(unfollow_user)
+            .service(list_followers)
+            .service(list_followees)
+            .service(create_challenge_code)
+            .service(bind_bilibili)
+            .service(start_recovery)
+            .service(finish_recovery)
+            .service(create_question)
+            .service(get_question)
+            .service(delete_question)
+            .service(update_question)
+            .service(apply_question_to_vtuber)
+            .service(remove_question_to_vtuber)
+            .service(get_question_applied)
+            .service(list_own_applications)
+            .service(review_application)
+            .service(list_applications)
+            .service(create_quiz_set)
+            .service(get_quiz_set)
+            .service(update_quiz_set)
+            .service(delete_quiz_set)
+            .service(list_quiz_sets)
+            .service(list_own_quiz_sets)
+            .service(start_attempt)
+            .service(get_attempt)
+            .service(submit_attempt)
+            .service(get_leaderboard)
+            .service(vote_to_question)
+            .default_service(actix_web::web::to(error::not_found)),
+    );
+}
+
+/// Id of the user logged in with this session.
+///
+/// Fails with `Error::Unauthenticated` when there is none, so clients know to
+/// show a login prompt rather than a permission error.
+pub(crate) fn current_user(session: &Session) -> Result<i32, Error> {
+    session
+        .get::<i32>("user")
+        .ok()
+        .flatten()
+        .ok_or(Error::Unauthenticated)
+}
+
+/// Offset and limit of a page, with the default limit filled in.
+pub(crate) fn check_page(page: &PageRequest) -> Result<(i64, i64), Error> {
+    let offset = page.offset.unwrap_or(0);
+    let mut errors = Vec::new();
+    if offset < 0 {
+        errors.push(FieldError::new("offset", "must not be negative"));
+    }
+    let limit = check_limit(page.limit, &mut errors);
+    if errors.is_empty() {
+        Ok((offset, limit))
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+/// Limit of a page listed with a cursor, such as the feed, with the default
+/// filled in.
+pub(crate) fn check_cursor_limit(limit: Option<i64>) -> Result<i64, Error> {
+    let mut errors = Vec::new();
+    let limit = check_limit(limit, &mut errors);
+    if errors.is_empty() {
+        Ok(limit)
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+fn check_limit(limit: Option<i64>, errors: &mut Vec<FieldError>) -> i64 {
+    let limit = limit.unwrap_or(PageRequest::DEFAULT_LIMIT);
+    if !(1..=PageRequest::MAX_LIMIT).contains(&limit) {
+        errors.push(FieldError::new(
+            "limit",
+            format!("must be between 1 and {}", PageRequest::MAX_LIMIT),
+        ));
+    }
+    limit
+}
@@ -1,13 +1,13 @@
 use actix_session::Session;
 // use actix_web::{delete, get, post, put};
 use actix_web::{HttpResponse, Result};
-use paperclip::actix::{delete, get, post, put, api_v2_operation, web};
-use sqlx::PgPool;
+use paperclip::actix::{api_v2_operation, delete, get, post, put, web};
 use vtuber_quiz_commons::models::*;
 
-use crate::db;
-use crate::error::Error;
+use super::{check_page, current_user};
+use crate::error::{Error, FieldError};
 use crate::hcaptcha::Hcaptcha;
+use crate::storage::SharedStorage;
 
 #[api_v2_operation(
     summary = "Create question",
@@ -15,54 +15,57 @@ use crate::hcaptcha::Hcaptcha;
     operation_id = "create_question",
     consumes = "application/json",
     produces = "application/json",
-    tags(Cats, Dogs, "Api reference"),
+    tags(Cats, Dogs, "Api reference")
 )]
 #[post("/question")]
 pub async fn create_question(
     req: web::Json<QuestionCreationRequest>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
     _hcaptcha: Hcaptcha,
 ) -> Result<HttpResponse> {
-    let creator = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
-    let qid = db::create_question(&pool, creator, req.into_inner()).await?;
-    Ok(HttpResponse::Ok().json(db::get_question(&pool, qid).await?))
+    let creator = current_user(&session)?;
+    if storage.get_user_by_id(creator).await?.blocked {
+        return Err(Error::Blocked.into());
+    }
+    let qid = storage.create_question(creator, req.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(storage.get_question(qid).await?))
 }
 
+/// Get a question. Its answer is left empty while a published quiz set asks
+/// it, except to its creator.
 #[api_v2_operation]
 #[get("/question/{qid}")]
-pub async fn get_question(qid: web::Path<i32>, pool: web::Data<PgPool>) -> Result<HttpResponse> {
-    let question = db::get_question(&pool, *qid).await?;
+pub async fn get_question(
+    qid: web::Path<i32>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let mut question = storage.get_question(*qid).await?;
     if question.deleted {
-        Ok(HttpResponse::NotFound().finish())
-    } else {
-        Ok(HttpResponse::Ok().json(question))
+        return Err(Error::NotFound.into());
     }
+    let viewer = current_user(&session).ok();
+    if viewer != Some(question.creator) && storage.question_in_quiz_set(*qid).await? {
+        question.answer.clear();
+    }
+    Ok(HttpResponse::Ok().json(question))
 }
 
 #[api_v2_operation]
 #[delete("/question/{qid}")]
 pub async fn delete_question(
     qid: web::Path<i32>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
 ) -> Result<HttpResponse> {
-    let user = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
-    let question = db::get_question(&pool, *qid).await?;
-    if question.creator == user {
-        db::delete_question(&pool, *qid).await?;
-        Ok(HttpResponse::NoContent().finish())
-    } else {
-        Ok(HttpResponse::Unauthorized().finish())
+    let user = current_user(&session)?;
+    let question = storage.get_question(*qid).await?;
+    if question.creator != user {
+        return Err(Error::Forbidden.into());
     }
+    storage.delete_question(*qid).await?;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 /// Fields that can be updated:
@@ -84,48 +87,45 @@ pub async fn delete_question(
 pub async fn update_question(
     req: web::Json<Question>,
     qid: web::Path<i32>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
 ) -> Result<HttpResponse> {
-    let user = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
+    let user = current_user(&session)?;
     let question = req.into_inner();
-    if *qid != question.id || !question.is_valid() {
-        return Ok(HttpResponse::BadRequest().finish());
+    if *qid != question.id {
+        return Err(Error::invalid("id", "does not match the path").into());
     }
-    let origin_question = db::get_question(&pool, *qid).await?;
+    if !question.is_valid() {
+        return Err(
+            Error::invalid("answer", "is not valid for the question type and choices").into(),
+        );
+    }
+    let origin_question = storage.get_question(*qid).await?;
     if origin_question.creator != user {
-        return Ok(HttpResponse::Unauthorized().finish());
+        return Err(Error::Forbidden.into());
     }
-    db::update_question(&pool, question).await?;
-    Ok(HttpResponse::Ok().json(db::get_question(&pool, *qid).await?))
+    storage.update_question(question).await?;
+    Ok(HttpResponse::Ok().json(storage.get_question(*qid).await?))
 }
 
 #[api_v2_operation]
 #[post("/question/{qid}/apply/{uid}")]
 pub async fn apply_question_to_vtuber(
     path: web::Path<(i32, i32)>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
 ) -> Result<HttpResponse> {
-    let user = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
+    let user = current_user(&session)?;
     let (qid, uid) = path.into_inner();
-    let question = db::get_question(&pool, qid).await?;
+    let question = storage.get_question(qid).await?;
     if question.creator != user {
-        return Ok(HttpResponse::Unauthorized().finish());
+        return Err(Error::Forbidden.into());
     }
-    let vtuber = db::get_user_by_id(&pool, uid).await?;
+    let vtuber = storage.get_user_by_id(uid).await?;
     if vtuber.role != UserRole::Vtuber {
-        return Ok(HttpResponse::BadRequest().finish());
+        return Err(Error::BadPath(FieldError::new("uid", "user is not a vtuber")).into());
     }
-    db::apply_question_to_vtuber(&pool, qid, uid).await?;
+    storage.apply_question_to_vtuber(qid, uid).await?;
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -133,24 +133,20 @@ pub async fn apply_question_to_vtuber(
 #[delete("/question/{qid}/apply/{uid}")]
 pub async fn remove_question_to_vtuber(
     path: web::Path<(i32, i32)>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
 ) -> Result<HttpResponse> {
-    let user = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
+    let user = current_user(&session)?;
     let (qid, uid) = path.into_inner();
-    let question = db::get_question(&pool, qid).await?;
+    let question = storage.get_question(qid).await?;
     if question.creator != user {
-        return Ok(HttpResponse::Unauthorized().finish());
+        return Err(Error::Forbidden.into());
     }
-    let vtuber = db::get_user_by_id(&pool, uid).await?;
+    let vtuber = storage.get_user_by_id(uid).await?;
     if vtuber.role != UserRole::Vtuber {
-        return Ok(HttpResponse::BadRequest().finish());
+        return Err(Error::BadPath(FieldError::new("uid", "user is not a vtuber")).into());
     }
-    db::remove_question_to_vtuber(&pool, qid, uid).await?;
+    storage.remove_question_to_vtuber(qid, uid).await?;
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -158,11 +154,81 @@ pub async fn remove_question_to_vtuber(
 #[get("/question/{qid}/apply")]
 pub async fn get_question_applied(
     qid: web::Path<i32>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
 ) -> Result<HttpResponse> {
-    let question = db::get_question(&pool, *qid).await?;
+    let question = storage.get_question(*qid).await?;
     if question.draft || question.deleted {
-        return Ok(HttpResponse::NotFound().finish());
+        return Err(Error::NotFound.into());
+    }
+    Ok(HttpResponse::Ok().json(storage.get_question_applied(*qid).await?))
+}
+
+/// Questions applied to the logged in vtuber, to review. Pinned first, then
+/// newest first.
+#[api_v2_operation]
+#[get("/user/self/applications")]
+pub async fn list_own_applications(
+    page: web::Query<ApplicationRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = current_user(&session)?;
+    let (offset, limit) = check_page(&PageRequest {
+        offset: page.offset,
+        limit: page.limit,
+    })?;
+    if storage.get_user_by_id(user).await?.role != UserRole::Vtuber {
+        return Err(Error::Forbidden.into());
     }
-    Ok(HttpResponse::Ok().json(db::get_question_applied(&pool, *qid).await))
+    let applications = storage
+        .list_applications(user, page.status, offset, limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(applications))
+}
+
+/// Approve, reject, hide or pin a question applied to the logged in vtuber.
+#[api_v2_operation]
+#[put("/user/self/applications/{qid}")]
+pub async fn review_application(
+    qid: web::Path<i32>,
+    req: web::Json<ReviewRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = current_user(&session)?;
+    if storage.get_user_by_id(user).await?.role != UserRole::Vtuber {
+        return Err(Error::Forbidden.into());
+    }
+    let application = storage.get_application(*qid, user).await?;
+    let status = req.status.unwrap_or(application.status);
+    if status == ApplicationStatus::Pending && application.status != ApplicationStatus::Pending {
+        return Err(
+            Error::invalid("status", "reviewed applications cannot be pending again").into(),
+        );
+    }
+    if req.pinned == Some(true) && status != ApplicationStatus::Approved {
+        return Err(Error::invalid("pinned", "only approved applications can be pinned").into());
+    }
+    let pinned = req.pinned.unwrap_or(application.pinned);
+    storage
+        .review_application(*qid, user, status, pinned)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Questions approved by a vtuber, which make up their quiz. Pinned first,
+/// then newest first.
+#[api_v2_operation]
+#[get("/user/by-id/{id}/applications")]
+pub async fn list_applications(
+    id: web::Path<i32>,
+    page: web::Query<PageRequest>,
+    storage: web::Data<SharedStorage>,
+) -> Result<HttpResponse> {
+    let (offset, limit) = check_page(&page)?;
+    storage.get_user_by_id(*id).await?;
+    let applications = storage
+        .list_applications(*id, Some(ApplicationStatus::Approved), offset, limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(applications))
 }
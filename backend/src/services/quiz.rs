@@ -0,0 +1,291 @@
+use actix_session::Session;
+use actix_web::{HttpResponse, Result};
+use chrono::{Duration, Utc};
+use paperclip::actix::{api_v2_operation, delete, get, post, put, web};
+use vtuber_quiz_commons::models::*;
+
+use super::{check_page, current_user};
+use crate::error::{Error, FieldError};
+use crate::storage::SharedStorage;
+
+const MAX_NAME_LEN: usize = 100;
+const MAX_QUESTIONS: usize = 100;
+const MAX_TIME_LIMIT_SECS: i32 = 24 * 60 * 60;
+/// Answers submitted this long after the deadline are still scored, to
+/// allow for the time they take to arrive.
+const GRACE_SECS: i64 = 5;
+
+/// Check a quiz set, its vtuber and its questions, which must not be deleted
+/// nor drafts of anyone but `owner`.
+async fn check_quiz_set(
+    storage: &SharedStorage,
+    owner: i32,
+    req: &QuizSetRequest,
+) -> Result<(), Error> {
+    let mut errors = Vec::new();
+    let name_len = req.name.trim().chars().count();
+    if name_len == 0 || name_len > MAX_NAME_LEN {
+        errors.push(FieldError::new(
+            "name",
+            format!("must be between 1 and {} characters", MAX_NAME_LEN),
+        ));
+    }
+    if req.questions.is_empty() || req.questions.len() > MAX_QUESTIONS {
+        errors.push(FieldError::new(
+            "questions",
+            format!("must have between 1 and {} questions", MAX_QUESTIONS),
+        ));
+    }
+    if req.pass_mark < 1 || req.pass_mark as usize > req.questions.len() {
+        errors.push(FieldError::new(
+            "pass_mark",
+            "must be between 1 and the number of questions",
+        ));
+    }
+    if req
+        .time_limit_secs
+        .is_some_and(|secs| !(1..=MAX_TIME_LIMIT_SECS).contains(&secs))
+    {
+        errors.push(FieldError::new(
+            "time_limit_secs",
+            format!("must be between 1 and {}", MAX_TIME_LIMIT_SECS),
+        ));
+    }
+    match storage.get_user_by_id(req.vtuber).await {
+        Ok(user) if user.role == UserRole::Vtuber => {}
+        Ok(_) | Err(Error::NotFound) => errors.push(FieldError::new("vtuber", "is not a vtuber")),
+        Err(e) => return Err(e),
+    }
+    for qid in &req.questions {
+        let usable = match storage.get_question(*qid).await {
+            Ok(question) => !question.deleted && (!question.draft || question.creator == owner),
+            Err(Error::NotFound) => false,
+            Err(e) => return Err(e),
+        };
+        if !usable {
+            errors.push(FieldError::new(
+                "questions",
+                format!("question {} does not exist", qid),
+            ));
+            break;
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+/// A quiz set visible to `viewer`, drafts only to their owner.
+async fn visible_quiz_set(
+    storage: &SharedStorage,
+    id: i32,
+    viewer: Option<i32>,
+) -> Result<QuizSet, Error> {
+    let quiz_set = storage.get_quiz_set(id).await?;
+    if quiz_set.draft && Some(quiz_set.owner) != viewer {
+        return Err(Error::NotFound);
+    }
+    Ok(quiz_set)
+}
+
+/// Create a quiz set about a vtuber. It is the official quiz of the vtuber
+/// when they create it themselves.
+#[api_v2_operation]
+#[post("/quiz-set")]
+pub async fn create_quiz_set(
+    req: web::Json<QuizSetRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let owner = current_user(&session)?;
+    if storage.get_user_by_id(owner).await?.blocked {
+        return Err(Error::Blocked.into());
+    }
+    check_quiz_set(&storage, owner, &req).await?;
+    let id = storage.create_quiz_set(owner, &req).await?;
+    Ok(HttpResponse::Ok().json(storage.get_quiz_set(id).await?))
+}
+
+#[api_v2_operation]
+#[get("/quiz-set/{id}")]
+pub async fn get_quiz_set(
+    id: web::Path<i32>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let viewer = current_user(&session).ok();
+    let quiz_set = visible_quiz_set(&storage, *id, viewer).await?;
+    Ok(HttpResponse::Ok().json(quiz_set))
+}
+
+/// Replace a quiz set, bumping its version. Attempts already started are
+/// scored against the version they started with.
+#[api_v2_operation]
+#[put("/quiz-set/{id}")]
+pub async fn update_quiz_set(
+    id: web::Path<i32>,
+    req: web::Json<QuizSetRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = current_user(&session)?;
+    if storage.get_quiz_set(*id).await?.owner != user {
+        return Err(Error::Forbidden.into());
+    }
+    check_quiz_set(&storage, user, &req).await?;
+    storage.update_quiz_set(*id, &req).await?;
+    Ok(HttpResponse::Ok().json(storage.get_quiz_set(*id).await?))
+}
+
+/// Delete a quiz set along with its attempts.
+#[api_v2_operation]
+#[delete("/quiz-set/{id}")]
+pub async fn delete_quiz_set(
+    id: web::Path<i32>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = current_user(&session)?;
+    if storage.get_quiz_set(*id).await?.owner != user {
+        return Err(Error::Forbidden.into());
+    }
+    storage.delete_quiz_set(*id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Published quiz sets about a vtuber, official first.
+#[api_v2_operation]
+#[get("/user/by-id/{id}/quiz-sets")]
+pub async fn list_quiz_sets(
+    id: web::Path<i32>,
+    page: web::Query<PageRequest>,
+    storage: web::Data<SharedStorage>,
+) -> Result<HttpResponse> {
+    let (offset, limit) = check_page(&page)?;
+    storage.get_user_by_id(*id).await?;
+    let quiz_sets = storage
+        .list_quiz_sets(Some(*id), None, false, offset, limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(quiz_sets))
+}
+
+/// Quiz sets of the logged in user, drafts included.
+#[api_v2_operation]
+#[get("/user/self/quiz-sets")]
+pub async fn list_own_quiz_sets(
+    page: web::Query<PageRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = current_user(&session)?;
+    let (offset, limit) = check_page(&page)?;
+    let quiz_sets = storage
+        .list_quiz_sets(None, Some(user), true, offset, limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(quiz_sets))
+}
+
+/// Start an attempt of the current version of a quiz set, returning its
+/// questions without their answers. Deleted questions and drafts are left
+/// out, failing if no question is left.
+#[api_v2_operation]
+#[post("/quiz-set/{id}/attempt")]
+pub async fn start_attempt(
+    id: web::Path<i32>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = current_user(&session)?;
+    let quiz_set = visible_quiz_set(&storage, *id, Some(user)).await?;
+    let mut questions = Vec::with_capacity(quiz_set.questions.len());
+    for qid in &quiz_set.questions {
+        let question = storage.get_question(*qid).await?;
+        if !question.deleted && !question.draft {
+            questions.push(QuizQuestion::from(&question));
+        }
+    }
+    if questions.is_empty() {
+        return Err(Error::invalid("quiz_set", "has no questions left to ask").into());
+    }
+    let ids: Vec<i32> = questions.iter().map(|q| q.id).collect();
+    let pass_mark = quiz_set.pass_mark.min(ids.len() as i32);
+    let deadline = quiz_set
+        .time_limit_secs
+        .map(|secs| Utc::now() + Duration::seconds(secs as i64));
+    let attempt = storage
+        .start_attempt(
+            quiz_set.id,
+            quiz_set.version,
+            pass_mark,
+            user,
+            &ids,
+            deadline,
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(StartedAttempt { attempt, questions }))
+}
+
+/// The best takers of a quiz set by their best score, then by who reached it
+/// first.
+#[api_v2_operation]
+#[get("/quiz-set/{id}/leaderboard")]
+pub async fn get_leaderboard(
+    id: web::Path<i32>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let viewer = current_user(&session).ok();
+    let quiz_set = visible_quiz_set(&storage, *id, viewer).await?;
+    Ok(HttpResponse::Ok().json(storage.leaderboard(quiz_set.id).await?))
+}
+
+/// An attempt of the logged in user, with its score once submitted.
+#[api_v2_operation]
+#[get("/attempt/{id}")]
+pub async fn get_attempt(
+    id: web::Path<i64>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = current_user(&session)?;
+    let attempt = storage.get_attempt(*id).await?;
+    if attempt.taker != user {
+        return Err(Error::Forbidden.into());
+    }
+    Ok(HttpResponse::Ok().json(attempt))
+}
+
+/// Submit the answers of an attempt, one per question in order. Answers
+/// submitted after the deadline score nothing.
+#[api_v2_operation]
+#[post("/attempt/{id}/answers")]
+pub async fn submit_attempt(
+    id: web::Path<i64>,
+    req: web::Json<AnswerSheet>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = current_user(&session)?;
+    let attempt = storage.get_attempt(*id).await?;
+    if attempt.taker != user {
+        return Err(Error::Forbidden.into());
+    }
+    if attempt.finished.is_some() {
+        return Err(Error::invalid("attempt", "has already been submitted").into());
+    }
+    if req.answers.len() != attempt.questions.len() {
+        return Err(Error::invalid("answers", "must answer every question").into());
+    }
+    let late = attempt
+        .deadline
+        .is_some_and(|deadline| Utc::now() > deadline + Duration::seconds(GRACE_SECS));
+    let mut correct = Vec::with_capacity(req.answers.len());
+    for (qid, answer) in attempt.questions.iter().zip(&req.answers) {
+        let question = storage.get_question(*qid).await?;
+        correct.push(!late && question.is_correct(answer));
+    }
+    let attempt = storage.finish_attempt(*id, &correct, req.share).await?;
+    Ok(HttpResponse::Ok().json(attempt))
+}
@@ -1,25 +1,34 @@
 use actix_session::Session;
-use actix_web::{HttpResponse, Result};
-use paperclip::actix::{api_v2_operation, delete, get, post, web};
-use sqlx::PgPool;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, Result};
+use paperclip::actix::{api_v2_operation, delete, get, post, put, web};
 use vtuber_quiz_commons::models::*;
 
-use crate::db;
-use crate::error::Error;
+use super::{check_cursor_limit, check_page, current_user};
+use crate::bilibili::{PostInfo, SharedBilibili};
+use crate::config;
+use crate::error::{Error, FieldError};
 use crate::hcaptcha::Hcaptcha;
+use crate::live::Broker;
+use crate::storage::SharedStorage;
+use crate::webhook;
 use std::str::FromStr;
 
 #[api_v2_operation]
 #[post("/user")]
 pub async fn register(
     req: web::Json<RegRequest>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
+    account: web::Data<config::Account>,
     session: Session,
     _hcaptcha: Hcaptcha,
 ) -> Result<HttpResponse> {
-    let id = db::create_user(&pool, req.username.as_str(), req.password.as_str()).await?;
+    account.check_registration(req.username.as_str(), req.password.as_str())?;
+    let id = storage
+        .create_user(req.username.as_str(), req.password.as_str())
+        .await?;
     session.insert("user", id)?;
-    Ok(HttpResponse::Ok().json(db::get_user_by_id(&pool, id).await?))
+    Ok(HttpResponse::Ok().json(storage.get_user_by_id(id).await?))
 }
 
 #[api_v2_operation]
@@ -27,11 +36,35 @@ pub async fn register(
 pub async fn login(
     username: web::Path<String>,
     req: web::Json<LoginRequest>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
+    throttle: web::Data<config::Login>,
+    http: HttpRequest,
     session: Session,
     _hcaptcha: Hcaptcha,
 ) -> Result<HttpResponse> {
-    let user = db::login(&pool, username.as_str(), req.password.as_str()).await?;
+    let user_key = format!("user:{}", username);
+    let ip_key = format!("ip:{}", throttle.client_addr(http.head()));
+    let mut retry_after = None;
+    for key in [&user_key, &ip_key] {
+        retry_after = retry_after.max(storage.login_blocked_secs(key).await?);
+    }
+    if let Some(retry_after) = retry_after {
+        return Err(Error::LoginThrottled { retry_after }.into());
+    }
+    let user = match storage
+        .login(username.as_str(), req.password.as_str())
+        .await
+    {
+        Err(Error::InvalidCredential) => {
+            record_login_failure(&storage, &throttle, &user_key, &ip_key).await?;
+            return Err(Error::InvalidCredential.into());
+        }
+        res => res?,
+    };
+    storage.clear_login_failures(&user_key).await?;
+    if user.blocked {
+        return Err(Error::Blocked.into());
+    }
     session.insert("user", user.id)?;
     Ok(HttpResponse::Ok().json(user))
 }
@@ -41,15 +74,11 @@ pub async fn login(
 pub async fn follow_user(
     id: web::Path<i32>,
     req: web::Json<FollowRequest>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
 ) -> Result<HttpResponse> {
-    let from = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
-    db::follow(&pool, from, *id, req.private).await?;
+    let from = current_user(&session)?;
+    storage.follow(from, *id, req.private).await?;
     Ok(HttpResponse::NoContent().finish())
 }
 
@@ -57,42 +86,383 @@ pub async fn follow_user(
 #[delete("/user/by-id/{id}/follow")]
 pub async fn unfollow_user(
     id: web::Path<i32>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
 ) -> Result<HttpResponse> {
-    let from = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
-    db::unfollow(&pool, from, *id).await?;
+    let from = current_user(&session)?;
+    storage.unfollow(from, *id).await?;
     Ok(HttpResponse::NoContent().finish())
 }
 
 #[api_v2_operation]
 #[get("/user/self")]
-pub async fn get_self(pool: web::Data<PgPool>, session: Session) -> Result<HttpResponse> {
-    let id = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
-    let user = db::get_user_by_id(&pool, id).await?;
-    Ok(HttpResponse::Ok().json(user))
+pub async fn get_self(storage: web::Data<SharedStorage>, session: Session) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    let user = storage.get_user_by_id(id).await?;
+    let follow_counts = storage.follow_counts(id, Some(id)).await?;
+    Ok(HttpResponse::Ok().json(SelfProfile {
+        user,
+        follow_counts,
+    }))
+}
+
+/// Questions published by the users followed, and questions applied to the
+/// vtubers followed, newest first.
+#[api_v2_operation]
+#[get("/user/self/feed")]
+pub async fn get_feed(
+    page: web::Query<FeedRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    let limit = check_cursor_limit(page.limit)?;
+    let feed = storage.feed(id, page.before, limit).await?;
+    Ok(HttpResponse::Ok().json(feed))
+}
+
+/// Notifications of the logged in user, newest first.
+#[api_v2_operation]
+#[get("/user/self/notifications")]
+pub async fn list_notifications(
+    page: web::Query<NotificationRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    let limit = check_cursor_limit(page.limit)?;
+    let unread_only = page.unread.unwrap_or(false);
+    let notifications = storage
+        .list_notifications(id, page.before, limit, unread_only)
+        .await?;
+    Ok(HttpResponse::Ok().json(notifications))
+}
+
+/// Notifications of the logged in user and events of their feed as they
+/// happen, as server-sent events. Follows made after connecting take effect
+/// on the next connection.
+#[api_v2_operation]
+#[get("/user/self/stream")]
+pub async fn stream_live(
+    storage: web::Data<SharedStorage>,
+    broker: web::Data<Broker>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    let followees = storage.followee_ids(id).await?;
+    let stream = broker.stream(id, followees.into_iter().collect());
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // keep reverse proxies such as nginx from buffering the stream
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(stream))
+}
+
+#[api_v2_operation]
+#[get("/user/self/notifications/unread")]
+pub async fn count_unread_notifications(
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    let unread = storage.unread_notifications(id).await?;
+    Ok(HttpResponse::Ok().json(UnreadCount { unread }))
+}
+
+#[api_v2_operation]
+#[post("/user/self/notifications/read")]
+pub async fn mark_notifications_read(
+    req: web::Json<MarkReadRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    storage
+        .mark_notifications_read(id, req.ids.as_deref())
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Register a webhook receiving the events of the logged in vtuber, see the
+/// `webhook` module for their format. The secret signing the deliveries is
+/// only returned here.
+#[api_v2_operation]
+#[post("/user/self/webhooks")]
+pub async fn create_webhook(
+    req: web::Json<WebhookRequest>,
+    storage: web::Data<SharedStorage>,
+    config: web::Data<config::Webhook>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    if storage.get_user_by_id(id).await?.role != UserRole::Vtuber {
+        return Err(Error::Forbidden.into());
+    }
+    webhook::check_url(&req.url, config.allow_private_hosts).await?;
+    if storage.list_webhooks(id).await?.len() >= config.max_per_user as usize {
+        return Err(Error::invalid(
+            "url",
+            format!("at most {} webhooks can be registered", config.max_per_user),
+        )
+        .into());
+    }
+    let secret = webhook::generate_secret();
+    let webhook = storage.create_webhook(id, &req.url, &secret).await?;
+    Ok(HttpResponse::Ok().json(CreatedWebhook { webhook, secret }))
+}
+
+#[api_v2_operation]
+#[get("/user/self/webhooks")]
+pub async fn list_webhooks(
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    Ok(HttpResponse::Ok().json(storage.list_webhooks(id).await?))
+}
+
+/// Remove a webhook of the logged in user along with its deliveries.
+#[api_v2_operation]
+#[delete("/user/self/webhooks/{id}")]
+pub async fn delete_webhook(
+    webhook: web::Path<i32>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    storage.delete_webhook(id, *webhook).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Deliveries to a webhook of the logged in user, newest first.
+#[api_v2_operation]
+#[get("/user/self/webhooks/{id}/deliveries")]
+pub async fn list_deliveries(
+    webhook: web::Path<i32>,
+    page: web::Query<DeliveryRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    let limit = check_cursor_limit(page.limit)?;
+    let deliveries = storage
+        .list_deliveries(id, *webhook, page.before, limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+/// Public profile of a user.
+#[api_v2_operation]
+#[get("/user/by-id/{id}")]
+pub async fn get_user_by_id(
+    id: web::Path<i32>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let viewer = current_user(&session).ok();
+    let profile = storage.get_profile(*id, viewer).await?;
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+/// Public profile of a user, looked up by username.
+#[api_v2_operation]
+#[get("/user/{username}")]
+pub async fn get_user_by_username(
+    username: web::Path<String>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let viewer = current_user(&session).ok();
+    let user = storage.get_user_by_username(&username).await?;
+    let profile = storage.get_profile(user.id, viewer).await?;
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+/// Followers of a user, newest first. Private follows are only listed for
+/// the follower.
+#[api_v2_operation]
+#[get("/user/by-id/{id}/followers")]
+pub async fn list_followers(
+    id: web::Path<i32>,
+    page: web::Query<PageRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let (offset, limit) = check_page(&page)?;
+    storage.get_user_by_id(*id).await?;
+    let viewer = current_user(&session).ok();
+    let follows = storage.list_followers(*id, viewer, offset, limit).await?;
+    Ok(HttpResponse::Ok().json(follows))
+}
+
+/// Users followed by a user, newest first. Private follows are only listed
+/// for the user themselves.
+#[api_v2_operation]
+#[get("/user/by-id/{id}/followees")]
+pub async fn list_followees(
+    id: web::Path<i32>,
+    page: web::Query<PageRequest>,
+    storage: web::Data<SharedStorage>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let (offset, limit) = check_page(&page)?;
+    storage.get_user_by_id(*id).await?;
+    let viewer = current_user(&session).ok();
+    let follows = storage.list_followees(*id, viewer, offset, limit).await?;
+    Ok(HttpResponse::Ok().json(follows))
+}
+
+#[api_v2_operation]
+#[put("/user/self/password")]
+pub async fn change_password(
+    req: web::Json<PasswordChangeRequest>,
+    storage: web::Data<SharedStorage>,
+    account: web::Data<config::Account>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    let user = storage.get_user_by_id(id).await?;
+    match storage
+        .login(user.username.as_str(), req.old_password.as_str())
+        .await
+    {
+        Err(Error::InvalidCredential) => {
+            return Err(Error::invalid("old_password", "is incorrect").into())
+        }
+        res => res?,
+    };
+    account.check_password(
+        "new_password",
+        req.new_password.as_str(),
+        user.username.as_str(),
+    )?;
+    storage.set_password(id, req.new_password.as_str()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[api_v2_operation]
+#[post("/user/self/bilibili")]
+pub async fn bind_bilibili(
+    req: web::Json<BindRequest>,
+    storage: web::Data<SharedStorage>,
+    bilibili: web::Data<SharedBilibili>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let id = current_user(&session)?;
+    let challenge = storage
+        .get_user_by_id(id)
+        .await?
+        .challenge
+        .ok_or_else(|| Error::invalid("post_id", "no verify code has been created"))?;
+    let post = fetch_post(&bilibili, req.post_id).await?;
+    if !post.content.contains(challenge.as_str()) {
+        return Err(Error::invalid("post_id", "does not contain the verify code").into());
+    }
+    let sender = &post.sender;
+    storage
+        .bind_bilibili(id, sender.uid as i64, &sender.name, &sender.face)
+        .await?;
+    Ok(HttpResponse::Ok().json(storage.get_bilibili(id).await?))
+}
+
+/// Start recovering an account by posting the returned code from the bilibili
+/// account bound to it.
+#[api_v2_operation]
+#[post("/user/{username}/recovery")]
+pub async fn start_recovery(
+    username: web::Path<String>,
+    storage: web::Data<SharedStorage>,
+    _hcaptcha: Hcaptcha,
+) -> Result<HttpResponse> {
+    let user = storage.get_user_by_username(username.as_str()).await?;
+    if user.blocked {
+        return Err(Error::Blocked.into());
+    }
+    // accounts without a bound bilibili account cannot be recovered
+    storage.get_bilibili(user.id).await?;
+    let code = storage.create_recovery(user.id).await?;
+    Ok(HttpResponse::Ok().json(ChallengeResponse::new(code.as_str())))
+}
+
+/// Finish recovering an account: set a new password and log in.
+#[api_v2_operation]
+#[put("/user/{username}/recovery")]
+pub async fn finish_recovery(
+    username: web::Path<String>,
+    req: web::Json<RecoveryRequest>,
+    storage: web::Data<SharedStorage>,
+    bilibili: web::Data<SharedBilibili>,
+    account: web::Data<config::Account>,
+    session: Session,
+) -> Result<HttpResponse> {
+    let user = storage.get_user_by_username(username.as_str()).await?;
+    if user.blocked {
+        return Err(Error::Blocked.into());
+    }
+    let code = storage.get_recovery_code(user.id).await?;
+    let bound = storage.get_bilibili(user.id).await?;
+    account.check_password(
+        "new_password",
+        req.new_password.as_str(),
+        user.username.as_str(),
+    )?;
+    let post = fetch_post(&bilibili, req.post_id).await?;
+    if post.sender.uid as i64 != bound.uid {
+        return Err(Error::invalid("post_id", "was not sent by the bound bilibili account").into());
+    }
+    if !post.content.contains(code.as_str()) {
+        return Err(Error::invalid("post_id", "does not contain the recovery code").into());
+    }
+    storage
+        .set_password(user.id, req.new_password.as_str())
+        .await?;
+    storage.delete_recovery(user.id).await?;
+    session.insert("user", user.id)?;
+    Ok(HttpResponse::Ok().json(storage.get_user_by_id(user.id).await?))
+}
+
+/// Count a failed login against both the username and the client address,
+/// blocking whichever has failed too often.
+async fn record_login_failure(
+    storage: &SharedStorage,
+    throttle: &config::Login,
+    user_key: &str,
+    ip_key: &str,
+) -> Result<(), Error> {
+    let failures = storage
+        .record_login_failure(user_key, throttle.reset_secs)
+        .await?;
+    if let Some(secs) = throttle.username_block_secs(failures) {
+        storage.block_login(user_key, secs).await?;
+    }
+    let failures = storage
+        .record_login_failure(ip_key, throttle.reset_secs)
+        .await?;
+    if let Some(secs) = throttle.ip_block_secs(failures) {
+        storage.block_login(ip_key, secs).await?;
+    }
+    Ok(())
+}
+
+async fn fetch_post(bilibili: &SharedBilibili, post_id: u64) -> Result<PostInfo, Error> {
+    bilibili.post(post_id).await.map_err(|e| {
+        if e.is_not_found() {
+            Error::invalid("post_id", "no such post")
+        } else {
+            e.into()
+        }
+    })
 }
 
 #[api_v2_operation]
 #[post("/user/self/bilbili/verify_code")]
 pub async fn create_challenge_code(
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
 ) -> Result<HttpResponse> {
-    let id = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
-    let challenge = db::create_or_replace_challenge(&pool, id).await?;
+    let id = current_user(&session)?;
+    let challenge = storage.create_or_replace_challenge(id).await?;
     Ok(HttpResponse::Ok().json(ChallengeResponse::new(challenge.as_str())))
 }
 
@@ -100,24 +470,24 @@ pub async fn create_challenge_code(
 #[post("/user/vote/{qid}/{action}")]
 pub async fn vote_to_question(
     path: web::Path<(i32, String)>,
-    pool: web::Data<PgPool>,
+    storage: web::Data<SharedStorage>,
     session: Session,
     _hcaptcha: Hcaptcha,
 ) -> Result<HttpResponse> {
-    let user = session
-        .get::<i32>("user")
-        .ok()
-        .flatten()
-        .ok_or(Error::InvalidCredential)?;
+    let user = current_user(&session)?;
     let (qid, action) = path.into_inner();
-    let question = db::get_question(&pool, qid).await?;
-    if question.creator == user || question.draft || question.deleted {
-        return Ok(HttpResponse::BadRequest().finish());
+    let action = VoteAction::from_str(action.as_str())
+        .map_err(|_| Error::BadPath(FieldError::new("action", "unknown vote action")))?;
+    if storage.get_user_by_id(user).await?.blocked {
+        return Err(Error::Blocked.into());
+    }
+    let question = storage.get_question(qid).await?;
+    if question.draft || question.deleted {
+        return Err(Error::NotFound.into());
     }
-    if let Ok(action) = VoteAction::from_str(action.as_str()) {
-        db::vote_to_question(&pool, user, qid, action).await?;
-        Ok(HttpResponse::NoContent().finish())
-    } else {
-        Ok(HttpResponse::BadRequest().finish())
+    if question.creator == user {
+        return Err(Error::invalid("qid", "cannot vote on your own question").into());
     }
+    storage.vote_to_question(user, qid, action).await?;
+    Ok(HttpResponse::NoContent().finish())
 }
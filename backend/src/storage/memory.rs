@@ -0,0 +1,1391 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use vtuber_quiz_commons::models::*;
+
+use super::*;
+use crate::error::FieldError;
+use crate::{config, db};
+
+/// Storage kept in process memory, mirroring the constraints of the Postgres schema.
+#[derive(Default)]
+pub struct MemoryStorage {
+    inner: Mutex<Inner>,
+    argon2: config::Argon2,
+}
+
+#[derive(Default)]
+struct Inner {
+    users: Vec<User>,
+    bilibili: Vec<Bilibili>,
+    recovery: Vec<Recovery>,
+    login_attempts: HashMap<String, LoginAttempt>,
+    buckets: HashMap<String, (f64, DateTime<Utc>)>,
+    questions: Vec<Question>,
+    votes: Vec<Vote>,
+    following: Vec<Following>,
+    apply_to: Vec<Application>,
+    events: Vec<Event>,
+    notifications: Vec<StoredNotification>,
+    webhooks: Vec<StoredWebhook>,
+    /// deliveries by the id of their webhook
+    deliveries: Vec<(i32, Delivery)>,
+    last_webhook: i32,
+    quiz_sets: Vec<QuizSet>,
+    attempts: Vec<Attempt>,
+}
+
+struct Recovery {
+    user: i32,
+    code: String,
+    expires: DateTime<Utc>,
+}
+
+struct Event {
+    id: i64,
+    kind: EventKind,
+    actor: i32,
+    question: Option<i32>,
+    vtuber: Option<i32>,
+    attempt: Option<i64>,
+    created: DateTime<Utc>,
+}
+
+struct StoredNotification {
+    id: i64,
+    recipient: i32,
+    kind: NotificationKind,
+    actor: Option<i32>,
+    question: Option<i32>,
+    vote: Option<VoteAction>,
+    read: bool,
+    created: DateTime<Utc>,
+}
+
+struct StoredWebhook {
+    owner: i32,
+    webhook: Webhook,
+    secret: String,
+}
+
+struct LoginAttempt {
+    failures: u32,
+    last_failure: DateTime<Utc>,
+    blocked_until: Option<DateTime<Utc>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash new passwords with `argon2` instead of the defaults.
+    pub fn with_argon2(mut self, argon2: config::Argon2) -> Self {
+        self.argon2 = argon2;
+        self
+    }
+
+    /// Set the role of a user, which is only possible through the admin CLI otherwise.
+    pub fn set_user_role(&self, id: i32, role: UserRole) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let user = inner.user_mut(id)?;
+        user.role = role;
+        user.updated = Utc::now();
+        Ok(())
+    }
+
+    /// Signing secret of a webhook, which the API only returns on creation.
+    pub fn webhook_secret(&self, id: i32) -> Option<String> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .webhooks
+            .iter()
+            .find(|w| w.webhook.id == id)
+            .map(|w| w.secret.clone())
+    }
+}
+
+impl Inner {
+    fn user_mut(&mut self, id: i32) -> Result<&mut User, Error> {
+        self.users
+            .iter_mut()
+            .find(|u| u.id == id)
+            .ok_or(Error::NotFound)
+    }
+
+    fn user_exists(&self, id: i32) -> bool {
+        self.users.iter().any(|u| u.id == id)
+    }
+
+    /// Follows matching `filter` and visible to `viewer`, newest first, as
+    /// entries for the user given by `other`.
+    fn follow_list(
+        &self,
+        filter: impl Fn(&Following) -> bool,
+        other: impl Fn(&Following) -> i32,
+        viewer: Option<i32>,
+        offset: i64,
+        limit: i64,
+    ) -> FollowList {
+        let mut follows: Vec<&Following> = self
+            .following
+            .iter()
+            .filter(|f| filter(f) && (!f.private || Some(f.follower) == viewer))
+            .collect();
+        follows.sort_by(|a, b| b.created.cmp(&a.created).then(other(a).cmp(&other(b))));
+        let total = follows.len() as i64;
+        let follows = follows
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .filter_map(|f| {
+                let user = self.users.iter().find(|u| u.id == other(f))?;
+                Some(FollowEntry {
+                    id: user.id,
+                    username: user.username.clone(),
+                    private: f.private,
+                    created: f.created,
+                })
+            })
+            .collect();
+        FollowList { total, follows }
+    }
+
+    fn question_mut(&mut self, qid: i32) -> Option<&mut Question> {
+        self.questions.iter_mut().find(|q| q.id == qid)
+    }
+
+    fn push_event(&mut self, kind: EventKind, actor: i32, question: i32, vtuber: Option<i32>) {
+        let id = self.events.len() as i64 + 1;
+        self.events.push(Event {
+            id,
+            kind,
+            actor,
+            question: Some(question),
+            vtuber,
+            attempt: None,
+            created: Utc::now(),
+        });
+    }
+
+    /// Add an approved application to the feed and tell the vtuber's
+    /// webhooks about it.
+    fn announce_application(&mut self, qid: i32, vtuber: i32) -> Result<(), Error> {
+        let question = self.question_mut(qid).ok_or(Error::NotFound)?;
+        let creator = question.creator;
+        let payload = serde_json::json!({
+            "question": qid,
+            "description": question.description,
+            "creator": creator,
+            "vtuber": vtuber,
+        });
+        self.push_event(EventKind::QuestionApplied, creator, qid, Some(vtuber));
+        self.queue_deliveries(vtuber, WebhookEvent::QuestionApplied, payload);
+        Ok(())
+    }
+
+    fn push_quiz_result(&mut self, attempt: &Attempt) {
+        let id = self.events.len() as i64 + 1;
+        self.events.push(Event {
+            id,
+            kind: EventKind::QuizResult,
+            actor: attempt.taker,
+            question: None,
+            vtuber: None,
+            attempt: Some(attempt.id),
+            created: Utc::now(),
+        });
+    }
+
+    fn notify(
+        &mut self,
+        recipient: i32,
+        kind: NotificationKind,
+        actor: Option<i32>,
+        question: Option<i32>,
+        vote: Option<VoteAction>,
+    ) {
+        let id = self.notifications.len() as i64 + 1;
+        self.notifications.push(StoredNotification {
+            id,
+            recipient,
+            kind,
+            actor,
+            question,
+            vote,
+            read: false,
+            created: Utc::now(),
+        });
+    }
+
+    /// Mirror the foreign keys and unique constraint of a quiz set.
+    fn check_quiz_set(&self, owner: i32, req: &QuizSetRequest) -> Result<(), Error> {
+        if !self.user_exists(owner)
+            || !self.user_exists(req.vtuber)
+            || !req
+                .questions
+                .iter()
+                .all(|qid| self.questions.iter().any(|q| q.id == *qid))
+        {
+            return Err(Error::NotFound);
+        }
+        let mut questions = req.questions.clone();
+        questions.sort_unstable();
+        questions.dedup();
+        if questions.len() != req.questions.len() {
+            return Err(Error::invalid(
+                "questions",
+                "must not contain a question twice",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Queue a delivery of an event to each webhook of `owner`.
+    fn queue_deliveries(&mut self, owner: i32, event: WebhookEvent, payload: serde_json::Value) {
+        let webhooks: Vec<i32> = self
+            .webhooks
+            .iter()
+            .filter(|w| w.owner == owner)
+            .map(|w| w.webhook.id)
+            .collect();
+        for webhook in webhooks {
+            let id = self.deliveries.len() as i64 + 1;
+            self.deliveries.push((
+                webhook,
+                Delivery {
+                    id,
+                    event,
+                    payload: payload.clone(),
+                    status: DeliveryStatus::Pending,
+                    attempts: 0,
+                    last_status: None,
+                    last_error: None,
+                    created: Utc::now(),
+                    delivered: None,
+                },
+            ));
+        }
+    }
+
+    fn username(&self, id: i32) -> Option<String> {
+        self.users
+            .iter()
+            .find(|u| u.id == id)
+            .map(|u| u.username.clone())
+    }
+
+    /// The best finished attempt of each taker of a quiz set, best first.
+    fn leaderboard(&self, quiz_set: i32) -> Vec<LeaderboardEntry> {
+        let mut best: Vec<LeaderboardEntry> = Vec::new();
+        for attempt in self.attempts.iter().filter(|a| a.quiz_set == quiz_set) {
+            let (score, finished) = match (attempt.score, attempt.finished) {
+                (Some(score), Some(finished)) => (score, finished),
+                _ => continue,
+            };
+            match best.iter_mut().find(|e| e.taker == attempt.taker) {
+                Some(entry) if (score, entry.finished) > (entry.score, finished) => {
+                    entry.score = score;
+                    entry.finished = finished;
+                }
+                Some(_) => {}
+                None => best.push(LeaderboardEntry {
+                    taker: attempt.taker,
+                    taker_name: self.username(attempt.taker).unwrap_or_default(),
+                    score,
+                    finished,
+                }),
+            }
+        }
+        best.sort_by_key(|e| (-e.score, e.finished, e.taker));
+        best.truncate(db::LEADERBOARD_SIZE as usize);
+        best
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryStorage {
+    async fn create_user(&self, username: &str, password: &str) -> Result<i32, Error> {
+        let hashed = db::hash_password(password.as_bytes(), &self.argon2)?;
+        if username.chars().count() > 20 {
+            return Err(Error::invalid("username", "must be at most 20 characters"));
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner.users.iter().any(|u| u.username == username) {
+            return Err(Error::ConflictUsername);
+        }
+        let id = inner.users.len() as i32 + 1;
+        let now = Utc::now();
+        inner.users.push(User {
+            id,
+            username: username.to_string(),
+            password: hashed,
+            challenge: None,
+            role: UserRole::Normal,
+            blocked: false,
+            reputation: 0,
+            created: now,
+            updated: now,
+        });
+        Ok(id)
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<User, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .users
+            .iter()
+            .find(|u| u.username == username)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<User, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .users
+            .iter()
+            .find(|u| u.id == id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn create_or_replace_challenge(&self, id: i32) -> Result<String, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let challenge = loop {
+            let challenge = db::generate_challenge_code();
+            if !inner
+                .users
+                .iter()
+                .any(|u| u.challenge.as_ref() == Some(&challenge))
+            {
+                break challenge;
+            }
+        };
+        if let Ok(user) = inner.user_mut(id) {
+            user.challenge = Some(challenge.clone());
+        }
+        Ok(challenge)
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<User, Error> {
+        let user = match self.get_user_by_username(username).await {
+            Err(Error::NotFound) => return Err(Error::InvalidCredential),
+            res => res?,
+        };
+        if !db::verify_password(user.password.as_str(), password.as_bytes())? {
+            return Err(Error::InvalidCredential);
+        }
+        if db::needs_rehash(user.password.as_str(), &self.argon2) {
+            self.set_password(user.id, password).await?;
+        }
+        Ok(user)
+    }
+
+    async fn set_password(&self, id: i32, password: &str) -> Result<(), Error> {
+        let hashed = db::hash_password(password.as_bytes(), &self.argon2)?;
+        let mut inner = self.inner.lock().unwrap();
+        let user = inner.user_mut(id)?;
+        user.password = hashed;
+        user.updated = Utc::now();
+        Ok(())
+    }
+
+    async fn get_profile(&self, id: i32, viewer: Option<i32>) -> Result<Profile, Error> {
+        let follow_counts = self.follow_counts(id, viewer).await?;
+        let inner = self.inner.lock().unwrap();
+        let user = inner
+            .users
+            .iter()
+            .find(|u| u.id == id)
+            .ok_or(Error::NotFound)?;
+        let bilibili = inner.bilibili.iter().find(|b| b.user == id);
+        let published = |qid: i32| {
+            inner
+                .questions
+                .iter()
+                .any(|q| q.id == qid && !q.draft && !q.deleted)
+        };
+        let questions = inner
+            .questions
+            .iter()
+            .filter(|q| q.creator == id && published(q.id))
+            .count() as i64;
+        let applied_questions = inner
+            .apply_to
+            .iter()
+            .filter(|a| a.vtuber == id && a.status.is_public() && published(a.question))
+            .count() as i64;
+        Ok(Profile {
+            id,
+            username: user.username.clone(),
+            role: user.role,
+            reputation: user.reputation,
+            bilibili_name: bilibili.map(|b| b.name.clone()),
+            bilibili_avatar: bilibili.map(|b| b.avatar.clone()),
+            questions,
+            follow_counts,
+            applied_questions: (user.role == UserRole::Vtuber).then_some(applied_questions),
+            created: user.created,
+        })
+    }
+}
+
+#[async_trait]
+impl BilibiliRepository for MemoryStorage {
+    async fn get_bilibili(&self, user: i32) -> Result<Bilibili, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .bilibili
+            .iter()
+            .find(|b| b.user == user)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn bind_bilibili(
+        &self,
+        user: i32,
+        uid: i64,
+        name: &str,
+        avatar: &str,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.user_mut(user)?;
+        if inner
+            .bilibili
+            .iter()
+            .any(|b| b.user != user && (b.uid == uid || b.name == name))
+        {
+            return Err(Error::ConflictBilibili);
+        }
+        inner.bilibili.retain(|b| b.user != user);
+        inner.bilibili.push(Bilibili {
+            uid,
+            name: name.to_string(),
+            avatar: avatar.to_string(),
+            user,
+            created: Utc::now(),
+        });
+        let user = inner.user_mut(user)?;
+        user.challenge = None;
+        user.updated = Utc::now();
+        Ok(())
+    }
+
+    async fn create_recovery(&self, user: i32) -> Result<String, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.user_mut(user)?;
+        let now = Utc::now();
+        if let Some(recovery) = inner
+            .recovery
+            .iter()
+            .find(|r| r.user == user && r.expires > now)
+        {
+            return Ok(recovery.code.clone());
+        }
+        let code = db::generate_challenge_code();
+        inner.recovery.retain(|r| r.user != user);
+        inner.recovery.push(Recovery {
+            user,
+            code: code.clone(),
+            expires: now + Duration::minutes(db::RECOVERY_TTL_MINUTES.into()),
+        });
+        Ok(code)
+    }
+
+    async fn get_recovery_code(&self, user: i32) -> Result<String, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .recovery
+            .iter()
+            .find(|r| r.user == user && r.expires > Utc::now())
+            .map(|r| r.code.clone())
+            .ok_or(Error::NotFound)
+    }
+
+    async fn delete_recovery(&self, user: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.recovery.retain(|r| r.user != user);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepository for MemoryStorage {
+    async fn login_blocked_secs(&self, key: &str) -> Result<Option<u64>, Error> {
+        let inner = self.inner.lock().unwrap();
+        let now = Utc::now();
+        Ok(inner
+            .login_attempts
+            .get(key)
+            .and_then(|a| a.blocked_until)
+            .filter(|until| *until > now)
+            .map(|until| ((until - now).num_milliseconds() as u64).div_ceil(1000)))
+    }
+
+    async fn login_failures(&self, key: &str, window_secs: u64) -> Result<u32, Error> {
+        let inner = self.inner.lock().unwrap();
+        let since = Utc::now() - Duration::seconds(window_secs as i64);
+        Ok(inner
+            .login_attempts
+            .get(key)
+            .filter(|a| a.last_failure > since)
+            .map_or(0, |a| a.failures))
+    }
+
+    async fn record_login_failure(&self, key: &str, reset_secs: u64) -> Result<u32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Utc::now();
+        let attempt = inner
+            .login_attempts
+            .entry(key.to_string())
+            .or_insert(LoginAttempt {
+                failures: 0,
+                last_failure: now,
+                blocked_until: None,
+            });
+        if attempt.last_failure <= now - Duration::seconds(reset_secs as i64) {
+            attempt.failures = 0;
+        }
+        attempt.failures += 1;
+        attempt.last_failure = now;
+        Ok(attempt.failures)
+    }
+
+    async fn block_login(&self, key: &str, secs: u64) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(attempt) = inner.login_attempts.get_mut(key) {
+            attempt.blocked_until = Some(Utc::now() + Duration::seconds(secs as i64));
+        }
+        Ok(())
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<(), Error> {
+        self.inner.lock().unwrap().login_attempts.remove(key);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateLimitRepository for MemoryStorage {
+    async fn take_token(&self, key: &str, bucket: Bucket) -> Result<Option<u64>, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Utc::now();
+        let (tokens, updated) = inner
+            .buckets
+            .entry(key.to_string())
+            .or_insert((bucket.capacity as f64, now));
+        let elapsed = (now - *updated).num_milliseconds() as f64 / 1000.0;
+        let (left, wait) = bucket.take(*tokens, elapsed);
+        *tokens = left;
+        *updated = now;
+        Ok(wait)
+    }
+}
+
+#[async_trait]
+impl QuestionRepository for MemoryStorage {
+    async fn create_question(
+        &self,
+        creator: i32,
+        question: QuestionCreationRequest,
+    ) -> Result<i32, Error> {
+        check_audiences(&question.audiences())?;
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.user_exists(creator) {
+            return Err(Error::NotFound);
+        }
+        let id = inner.questions.len() as i32 + 1;
+        let content = &question.content;
+        let now = Utc::now();
+        inner.questions.push(Question {
+            id,
+            creator,
+            description: content.description().to_string(),
+            choices: content.choices(),
+            answer: content.answer(),
+            question_type: content.ty(),
+            audiences: question.audiences(),
+            draft: question.draft,
+            deleted: false,
+            created: now,
+            updated: now,
+        });
+        if !question.draft {
+            inner.push_event(EventKind::QuestionPublished, creator, id, None);
+        }
+        Ok(id)
+    }
+
+    async fn update_question(&self, question: Question) -> Result<(), Error> {
+        check_audiences(&question.audiences)?;
+        let mut inner = self.inner.lock().unwrap();
+        let (qid, draft) = (question.id, question.draft);
+        let creator = match inner.question_mut(qid) {
+            Some(origin) => {
+                origin.description = question.description;
+                origin.choices = question.choices;
+                origin.answer = question.answer;
+                origin.audiences = question.audiences;
+                origin.draft = question.draft;
+                origin.updated = Utc::now();
+                origin.creator
+            }
+            None => return Ok(()),
+        };
+        let published = inner
+            .events
+            .iter()
+            .any(|e| e.kind == EventKind::QuestionPublished && e.question == Some(qid));
+        if !draft && !published {
+            inner.push_event(EventKind::QuestionPublished, creator, qid, None);
+        }
+        Ok(())
+    }
+
+    async fn get_question(&self, qid: i32) -> Result<Question, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .questions
+            .iter()
+            .find(|q| q.id == qid)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn delete_question(&self, qid: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(question) = inner.question_mut(qid) {
+            question.deleted = true;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VoteRepository for MemoryStorage {
+    async fn vote_to_question(&self, uid: i32, qid: i32, action: VoteAction) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.user_exists(uid) || inner.question_mut(qid).is_none() {
+            return Err(Error::NotFound);
+        }
+        if inner
+            .votes
+            .iter()
+            .any(|v| v.voter == uid && v.question == qid)
+        {
+            return Err(Error::ConflictVote);
+        }
+        let id = inner.votes.len() as i32 + 1;
+        inner.votes.push(Vote {
+            id,
+            voter: uid,
+            question: qid,
+            action,
+            created: Utc::now(),
+        });
+        let creator = inner.question_mut(qid).unwrap().creator;
+        if creator != uid {
+            inner.notify(
+                creator,
+                NotificationKind::Voted,
+                Some(uid),
+                Some(qid),
+                Some(action),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FollowRepository for MemoryStorage {
+    async fn follow(&self, from: i32, to: i32, private: bool) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if from == to {
+            return Err(Error::BadPath(FieldError::new(
+                "id",
+                "cannot follow yourself",
+            )));
+        }
+        if !inner.user_exists(from) || !inner.user_exists(to) {
+            return Err(Error::NotFound);
+        }
+        if inner
+            .following
+            .iter()
+            .any(|f| f.follower == from && f.followee == to)
+        {
+            return Ok(());
+        }
+        inner.following.push(Following {
+            follower: from,
+            followee: to,
+            private,
+            created: Utc::now(),
+        });
+        if !private {
+            inner.notify(to, NotificationKind::Followed, Some(from), None, None);
+        }
+        Ok(())
+    }
+
+    async fn unfollow(&self, from: i32, to: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .following
+            .retain(|f| !(f.follower == from && f.followee == to));
+        Ok(())
+    }
+
+    async fn list_followers(
+        &self,
+        user: i32,
+        viewer: Option<i32>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<FollowList, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.follow_list(
+            |f| f.followee == user,
+            |f| f.follower,
+            viewer,
+            offset,
+            limit,
+        ))
+    }
+
+    async fn list_followees(
+        &self,
+        user: i32,
+        viewer: Option<i32>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<FollowList, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.follow_list(
+            |f| f.follower == user,
+            |f| f.followee,
+            viewer,
+            offset,
+            limit,
+        ))
+    }
+
+    async fn follow_counts(&self, user: i32, viewer: Option<i32>) -> Result<FollowCounts, Error> {
+        let inner = self.inner.lock().unwrap();
+        let visible: Vec<&Following> = inner
+            .following
+            .iter()
+            .filter(|f| !f.private || Some(f.follower) == viewer)
+            .collect();
+        Ok(FollowCounts {
+            followers: visible.iter().filter(|f| f.followee == user).count() as i64,
+            followees: visible.iter().filter(|f| f.follower == user).count() as i64,
+        })
+    }
+
+    async fn followee_ids(&self, user: i32) -> Result<Vec<i32>, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .following
+            .iter()
+            .filter(|f| f.follower == user)
+            .map(|f| f.followee)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ApplicationRepository for MemoryStorage {
+    async fn apply_question_to_vtuber(&self, qid: i32, uid: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.user_exists(uid) || inner.question_mut(qid).is_none() {
+            return Err(Error::NotFound);
+        }
+        if !inner
+            .apply_to
+            .iter()
+            .any(|a| a.question == qid && a.vtuber == uid)
+        {
+            let creator = inner.question_mut(qid).unwrap().creator;
+            inner.apply_to.push(Application {
+                question: qid,
+                vtuber: uid,
+                status: if creator == uid {
+                    ApplicationStatus::Approved
+                } else {
+                    ApplicationStatus::Pending
+                },
+                pinned: false,
+                created: Utc::now(),
+                reviewed: None,
+            });
+            if uid == creator {
+                inner.announce_application(qid, uid)?;
+            } else {
+                inner.notify(
+                    uid,
+                    NotificationKind::Applied,
+                    Some(creator),
+                    Some(qid),
+                    None,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove_question_to_vtuber(&self, qid: i32, uid: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let reviewed = inner.apply_to.iter().any(|a| {
+            a.question == qid
+                && a.vtuber == uid
+                && matches!(
+                    a.status,
+                    ApplicationStatus::Rejected | ApplicationStatus::Hidden
+                )
+        });
+        if reviewed {
+            return Ok(());
+        }
+        inner
+            .apply_to
+            .retain(|a| !(a.question == qid && a.vtuber == uid));
+        inner.events.retain(|e| {
+            !(e.kind == EventKind::QuestionApplied
+                && e.question == Some(qid)
+                && e.vtuber == Some(uid))
+        });
+        Ok(())
+    }
+
+    async fn get_question_applied(&self, qid: i32) -> Result<Vec<i32>, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .apply_to
+            .iter()
+            .filter(|a| a.question == qid && a.status.is_public())
+            .map(|a| a.vtuber)
+            .collect())
+    }
+
+    async fn get_application(&self, qid: i32, vtuber: i32) -> Result<Application, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .apply_to
+            .iter()
+            .find(|a| a.question == qid && a.vtuber == vtuber)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn review_application(
+        &self,
+        qid: i32,
+        vtuber: i32,
+        status: ApplicationStatus,
+        pinned: bool,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let application = inner
+            .apply_to
+            .iter_mut()
+            .find(|a| a.question == qid && a.vtuber == vtuber)
+            .ok_or(Error::NotFound)?;
+        application.status = status;
+        application.pinned = pinned && status == ApplicationStatus::Approved;
+        application.reviewed = Some(Utc::now());
+        let announced = inner.events.iter().any(|e| {
+            e.kind == EventKind::QuestionApplied
+                && e.question == Some(qid)
+                && e.vtuber == Some(vtuber)
+        });
+        if status == ApplicationStatus::Approved && !announced {
+            inner.announce_application(qid, vtuber)?;
+        }
+        Ok(())
+    }
+
+    async fn list_applications(
+        &self,
+        vtuber: i32,
+        status: Option<ApplicationStatus>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<ApplicationList, Error> {
+        let inner = self.inner.lock().unwrap();
+        let mut applications: Vec<ApplicationEntry> = inner
+            .apply_to
+            .iter()
+            .filter(|a| a.vtuber == vtuber && status.is_none_or(|s| a.status == s))
+            .filter_map(|a| {
+                let question = inner
+                    .questions
+                    .iter()
+                    .find(|q| q.id == a.question && !q.draft && !q.deleted)?;
+                Some(ApplicationEntry {
+                    question: a.question,
+                    description: question.description.clone(),
+                    creator: question.creator,
+                    creator_name: inner.username(question.creator)?,
+                    status: a.status,
+                    pinned: a.pinned,
+                    created: a.created,
+                    reviewed: a.reviewed,
+                })
+            })
+            .collect();
+        applications.sort_by(|a, b| {
+            b.pinned
+                .cmp(&a.pinned)
+                .then(b.created.cmp(&a.created))
+                .then(b.question.cmp(&a.question))
+        });
+        let total = applications.len() as i64;
+        let applications = applications
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect();
+        Ok(ApplicationList {
+            total,
+            applications,
+        })
+    }
+}
+
+#[async_trait]
+impl EventRepository for MemoryStorage {
+    async fn feed(&self, user: i32, before: Option<i64>, limit: i64) -> Result<Feed, Error> {
+        let inner = self.inner.lock().unwrap();
+        let followed = |id: Option<i32>| {
+            inner
+                .following
+                .iter()
+                .any(|f| f.follower == user && Some(f.followee) == id)
+        };
+        let published = |qid: Option<i32>| match qid {
+            Some(qid) => inner
+                .questions
+                .iter()
+                .any(|q| q.id == qid && !q.draft && !q.deleted),
+            None => true,
+        };
+        let withdrawn = |e: &Event| {
+            inner.apply_to.iter().any(|a| {
+                Some(a.question) == e.question
+                    && Some(a.vtuber) == e.vtuber
+                    && !a.status.is_public()
+            })
+        };
+        // the shared attempt and its quiz set, gone along with the quiz set
+        let result = |e: &Event| {
+            let attempt = inner.attempts.iter().find(|a| Some(a.id) == e.attempt)?;
+            let quiz_set = inner.quiz_sets.iter().find(|s| s.id == attempt.quiz_set)?;
+            Some((attempt, quiz_set))
+        };
+        let events: Vec<FeedEvent> = inner
+            .events
+            .iter()
+            .rev()
+            .filter(|e| before.is_none_or(|before| e.id < before))
+            .filter(|e| (followed(Some(e.actor)) || followed(e.vtuber)) && published(e.question))
+            .filter(|e| !withdrawn(e))
+            .filter(|e| e.attempt.is_none() || result(e).is_some_and(|(_, s)| !s.draft))
+            .take(limit as usize)
+            .filter_map(|e| {
+                let result = result(e);
+                Some(FeedEvent {
+                    id: e.id,
+                    kind: e.kind,
+                    actor: e.actor,
+                    actor_name: inner.username(e.actor)?,
+                    question: e.question,
+                    vtuber: e.vtuber,
+                    vtuber_name: e.vtuber.and_then(|id| inner.username(id)),
+                    attempt: e.attempt,
+                    quiz_set: result.map(|(_, s)| s.id),
+                    quiz_set_name: result.map(|(_, s)| s.name.clone()),
+                    score: result.and_then(|(a, _)| a.score),
+                    passed: result.and_then(|(a, _)| a.passed),
+                    created: e.created,
+                })
+            })
+            .collect();
+        let next = match events.last() {
+            Some(last) if events.len() as i64 == limit => Some(last.id),
+            _ => None,
+        };
+        Ok(Feed { events, next })
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for MemoryStorage {
+    async fn list_notifications(
+        &self,
+        user: i32,
+        before: Option<i64>,
+        limit: i64,
+        unread_only: bool,
+    ) -> Result<NotificationList, Error> {
+        let inner = self.inner.lock().unwrap();
+        let notifications: Vec<Notification> = inner
+            .notifications
+            .iter()
+            .rev()
+            .filter(|n| n.recipient == user && !(unread_only && n.read))
+            .filter(|n| before.is_none_or(|before| n.id < before))
+            .take(limit as usize)
+            .map(|n| Notification {
+                id: n.id,
+                kind: n.kind,
+                actor: n.actor,
+                actor_name: n.actor.and_then(|id| inner.username(id)),
+                question: n.question,
+                vote: n.vote,
+                read: n.read,
+                created: n.created,
+            })
+            .collect();
+        let next = match notifications.last() {
+            Some(last) if notifications.len() as i64 == limit => Some(last.id),
+            _ => None,
+        };
+        Ok(NotificationList {
+            notifications,
+            next,
+        })
+    }
+
+    async fn unread_notifications(&self, user: i32) -> Result<i64, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .notifications
+            .iter()
+            .filter(|n| n.recipient == user && !n.read)
+            .count() as i64)
+    }
+
+    async fn mark_notifications_read(&self, user: i32, ids: Option<&[i64]>) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        for n in inner.notifications.iter_mut() {
+            if n.recipient == user && ids.is_none_or(|ids| ids.contains(&n.id)) {
+                n.read = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for MemoryStorage {
+    async fn create_webhook(&self, owner: i32, url: &str, secret: &str) -> Result<Webhook, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.user_exists(owner) {
+            return Err(Error::NotFound);
+        }
+        if inner
+            .webhooks
+            .iter()
+            .any(|w| w.owner == owner && w.webhook.url == url)
+        {
+            return Err(Error::invalid("url", "is already registered"));
+        }
+        inner.last_webhook += 1;
+        let webhook = Webhook {
+            id: inner.last_webhook,
+            url: url.to_string(),
+            created: Utc::now(),
+        };
+        inner.webhooks.push(StoredWebhook {
+            owner,
+            webhook: webhook.clone(),
+            secret: secret.to_string(),
+        });
+        Ok(webhook)
+    }
+
+    async fn list_webhooks(&self, owner: i32) -> Result<Vec<Webhook>, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .webhooks
+            .iter()
+            .filter(|w| w.owner == owner)
+            .map(|w| w.webhook.clone())
+            .collect())
+    }
+
+    async fn delete_webhook(&self, owner: i32, id: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.webhooks.len();
+        inner
+            .webhooks
+            .retain(|w| !(w.owner == owner && w.webhook.id == id));
+        if inner.webhooks.len() == len {
+            return Err(Error::NotFound);
+        }
+        inner.deliveries.retain(|(webhook, _)| *webhook != id);
+        Ok(())
+    }
+
+    async fn list_deliveries(
+        &self,
+        owner: i32,
+        webhook: i32,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<DeliveryList, Error> {
+        let inner = self.inner.lock().unwrap();
+        if !inner
+            .webhooks
+            .iter()
+            .any(|w| w.owner == owner && w.webhook.id == webhook)
+        {
+            return Err(Error::NotFound);
+        }
+        let deliveries: Vec<Delivery> = inner
+            .deliveries
+            .iter()
+            .rev()
+            .filter(|(w, d)| *w == webhook && before.is_none_or(|before| d.id < before))
+            .take(limit as usize)
+            .map(|(_, d)| d.clone())
+            .collect();
+        let next = match deliveries.last() {
+            Some(last) if deliveries.len() as i64 == limit => Some(last.id),
+            _ => None,
+        };
+        Ok(DeliveryList { deliveries, next })
+    }
+}
+
+#[async_trait]
+impl QuizRepository for MemoryStorage {
+    async fn create_quiz_set(&self, owner: i32, req: &QuizSetRequest) -> Result<i32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.check_quiz_set(owner, req)?;
+        let id = inner.quiz_sets.last().map_or(1, |s| s.id + 1);
+        let now = Utc::now();
+        inner.quiz_sets.push(QuizSet {
+            id,
+            owner,
+            vtuber: req.vtuber,
+            official: owner == req.vtuber,
+            name: req.name.clone(),
+            description: req.description.clone(),
+            questions: req.questions.clone(),
+            version: 1,
+            time_limit_secs: req.time_limit_secs,
+            pass_mark: req.pass_mark,
+            draft: req.draft,
+            created: now,
+            updated: now,
+        });
+        Ok(id)
+    }
+
+    async fn get_quiz_set(&self, id: i32) -> Result<QuizSet, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .quiz_sets
+            .iter()
+            .find(|s| s.id == id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn update_quiz_set(&self, id: i32, req: &QuizSetRequest) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let owner = inner
+            .quiz_sets
+            .iter()
+            .find(|s| s.id == id)
+            .ok_or(Error::NotFound)?
+            .owner;
+        inner.check_quiz_set(owner, req)?;
+        let quiz_set = inner.quiz_sets.iter_mut().find(|s| s.id == id).unwrap();
+        quiz_set.vtuber = req.vtuber;
+        quiz_set.official = owner == req.vtuber;
+        quiz_set.name = req.name.clone();
+        quiz_set.description = req.description.clone();
+        quiz_set.questions = req.questions.clone();
+        quiz_set.version += 1;
+        quiz_set.time_limit_secs = req.time_limit_secs;
+        quiz_set.pass_mark = req.pass_mark;
+        quiz_set.draft = req.draft;
+        quiz_set.updated = Utc::now();
+        Ok(())
+    }
+
+    async fn delete_quiz_set(&self, id: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.quiz_sets.len();
+        inner.quiz_sets.retain(|s| s.id != id);
+        if inner.quiz_sets.len() == len {
+            return Err(Error::NotFound);
+        }
+        inner.attempts.retain(|a| a.quiz_set != id);
+        Ok(())
+    }
+
+    async fn list_quiz_sets(
+        &self,
+        vtuber: Option<i32>,
+        owner: Option<i32>,
+        drafts: bool,
+        offset: i64,
+        limit: i64,
+    ) -> Result<QuizSetList, Error> {
+        let inner = self.inner.lock().unwrap();
+        let mut quiz_sets: Vec<&QuizSet> = inner
+            .quiz_sets
+            .iter()
+            .filter(|s| vtuber.is_none_or(|id| s.vtuber == id))
+            .filter(|s| owner.is_none_or(|id| s.owner == id))
+            .filter(|s| drafts || !s.draft)
+            .collect();
+        quiz_sets.sort_by(|a, b| {
+            b.official
+                .cmp(&a.official)
+                .then(b.updated.cmp(&a.updated))
+                .then(b.id.cmp(&a.id))
+        });
+        let total = quiz_sets.len() as i64;
+        let quiz_sets = quiz_sets
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect();
+        Ok(QuizSetList { total, quiz_sets })
+    }
+
+    async fn question_in_quiz_set(&self, qid: i32) -> Result<bool, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .quiz_sets
+            .iter()
+            .any(|s| !s.draft && s.questions.contains(&qid)))
+    }
+
+    async fn start_attempt(
+        &self,
+        quiz_set: i32,
+        version: i32,
+        pass_mark: i32,
+        taker: i32,
+        questions: &[i32],
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Attempt, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.user_exists(taker) || !inner.quiz_sets.iter().any(|s| s.id == quiz_set) {
+            return Err(Error::NotFound);
+        }
+        let attempt = Attempt {
+            id: inner.attempts.len() as i64 + 1,
+            quiz_set,
+            version,
+            pass_mark,
+            taker,
+            questions: questions.to_vec(),
+            started: Utc::now(),
+            deadline,
+            finished: None,
+            correct: None,
+            score: None,
+            passed: None,
+            shared: false,
+        };
+        inner.attempts.push(attempt.clone());
+        Ok(attempt)
+    }
+
+    async fn get_attempt(&self, id: i64) -> Result<Attempt, Error> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .attempts
+            .iter()
+            .find(|a| a.id == id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn finish_attempt(
+        &self,
+        id: i64,
+        correct: &[bool],
+        shared: bool,
+    ) -> Result<Attempt, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let quiz_set = inner
+            .attempts
+            .iter()
+            .find(|a| a.id == id)
+            .ok_or(Error::NotFound)?
+            .quiz_set;
+        let before = inner.leaderboard(quiz_set);
+        let attempt = inner.attempts.iter_mut().find(|a| a.id == id).unwrap();
+        if attempt.finished.is_some() {
+            return Err(Error::invalid("attempt", "has already been submitted"));
+        }
+        attempt.finished = Some(Utc::now());
+        attempt.correct = Some(correct.to_vec());
+        let score = correct.iter().filter(|c| **c).count() as i32;
+        attempt.score = Some(score);
+        attempt.passed = Some(score >= attempt.pass_mark);
+        attempt.shared = shared;
+        let attempt = attempt.clone();
+        let quiz_set = inner
+            .quiz_sets
+            .iter()
+            .find(|s| s.id == attempt.quiz_set)
+            .unwrap();
+        let (vtuber, name, draft) = (quiz_set.vtuber, quiz_set.name.clone(), quiz_set.draft);
+        let payload = serde_json::json!({
+            "attempt": attempt.id,
+            "quiz_set": quiz_set.id,
+            "name": quiz_set.name,
+            "version": attempt.version,
+            "taker": attempt.taker,
+            "score": attempt.score,
+            "questions": attempt.questions.len(),
+            "passed": attempt.passed,
+        });
+        inner.queue_deliveries(vtuber, WebhookEvent::AttemptFinished, payload);
+        let after = inner.leaderboard(attempt.quiz_set);
+        if !draft && after != before {
+            let payload = serde_json::json!({
+                "quiz_set": attempt.quiz_set,
+                "name": name,
+                "entries": after,
+            });
+            inner.queue_deliveries(vtuber, WebhookEvent::LeaderboardChanged, payload);
+        }
+        if shared && !draft {
+            inner.push_quiz_result(&attempt);
+        }
+        Ok(attempt)
+    }
+
+    async fn leaderboard(&self, quiz_set: i32) -> Result<Leaderboard, Error> {
+        let inner = self.inner.lock().unwrap();
+        let entries = inner.leaderboard(quiz_set);
+        Ok(Leaderboard { quiz_set, entries })
+    }
+}
+
+/// `audiences` is a `varchar(10)[]` column.
+fn check_audiences(audiences: &[String]) -> Result<(), Error> {
+    if audiences.iter().any(|a| a.chars().count() > 10) {
+        return Err(Error::invalid(
+            "audiences",
+            "must be at most 10 characters each",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,277 @@
+//! Storage used by the HTTP handlers.
+//!
+//! Handlers take a [`SharedStorage`] from `web::Data` instead of a `PgPool`, so that
+//! they can run against [`MemoryStorage`] in tests. [`PgStorage`] is backed by the
+//! functions in [`crate::db`].
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use vtuber_quiz_commons::models::*;
+
+use crate::error::Error;
+use crate::rate_limit::Bucket;
+
+mod memory;
+mod postgres;
+
+pub use memory::MemoryStorage;
+pub use postgres::PgStorage;
+
+pub type SharedStorage = Arc<dyn Storage>;
+
+#[async_trait]
+pub trait UserRepository {
+    async fn create_user(&self, username: &str, password: &str) -> Result<i32, Error>;
+    async fn get_user_by_username(&self, username: &str) -> Result<User, Error>;
+    async fn get_user_by_id(&self, id: i32) -> Result<User, Error>;
+    async fn create_or_replace_challenge(&self, id: i32) -> Result<String, Error>;
+    async fn login(&self, username: &str, password: &str) -> Result<User, Error>;
+    async fn set_password(&self, id: i32, password: &str) -> Result<(), Error>;
+    /// Public profile of a user, with follow counts as seen by `viewer`.
+    async fn get_profile(&self, id: i32, viewer: Option<i32>) -> Result<Profile, Error>;
+}
+
+/// Bilibili accounts bound to users, and account recovery through them.
+#[async_trait]
+pub trait BilibiliRepository {
+    async fn get_bilibili(&self, user: i32) -> Result<Bilibili, Error>;
+    async fn bind_bilibili(
+        &self,
+        user: i32,
+        uid: i64,
+        name: &str,
+        avatar: &str,
+    ) -> Result<(), Error>;
+    /// Start a recovery, keeping the code of an unexpired one.
+    async fn create_recovery(&self, user: i32) -> Result<String, Error>;
+    async fn get_recovery_code(&self, user: i32) -> Result<String, Error>;
+    async fn delete_recovery(&self, user: i32) -> Result<(), Error>;
+}
+
+/// Failed logins, counted per key such as `user:<username>` or `ip:<address>`.
+#[async_trait]
+pub trait LoginAttemptRepository {
+    /// Seconds until logins for `key` are allowed again, if blocked.
+    async fn login_blocked_secs(&self, key: &str) -> Result<Option<u64>, Error>;
+    /// Consecutive failures for `key`, if the last one is at most `window_secs` ago.
+    async fn login_failures(&self, key: &str, window_secs: u64) -> Result<u32, Error>;
+    /// Count a failed login and return the number of consecutive failures,
+    /// starting over if the last one is more than `reset_secs` ago.
+    async fn record_login_failure(&self, key: &str, reset_secs: u64) -> Result<u32, Error>;
+    async fn block_login(&self, key: &str, secs: u64) -> Result<(), Error>;
+    async fn clear_login_failures(&self, key: &str) -> Result<(), Error>;
+}
+
+/// Token buckets of the rate limiter.
+#[async_trait]
+pub trait RateLimitRepository {
+    /// Take a token from the bucket `key`, created full if missing. Returns
+    /// the seconds to wait if the bucket is empty.
+    async fn take_token(&self, key: &str, bucket: Bucket) -> Result<Option<u64>, Error>;
+}
+
+#[async_trait]
+pub trait QuestionRepository {
+    async fn create_question(
+        &self,
+        creator: i32,
+        question: QuestionCreationRequest,
+    ) -> Result<i32, Error>;
+    async fn update_question(&self, question: Question) -> Result<(), Error>;
+    async fn get_question(&self, qid: i32) -> Result<Question, Error>;
+    async fn delete_question(&self, qid: i32) -> Result<(), Error>;
+}
+
+#[async_trait]
+pub trait VoteRepository {
+    async fn vote_to_question(&self, uid: i32, qid: i32, action: VoteAction) -> Result<(), Error>;
+}
+
+#[async_trait]
+pub trait FollowRepository {
+    async fn follow(&self, from: i32, to: i32, private: bool) -> Result<(), Error>;
+    async fn unfollow(&self, from: i32, to: i32) -> Result<(), Error>;
+    /// Followers of `user`, leaving out private follows unless `viewer` is
+    /// the follower.
+    async fn list_followers(
+        &self,
+        user: i32,
+        viewer: Option<i32>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<FollowList, Error>;
+    /// Users followed by `user`, leaving out private follows unless `viewer`
+    /// is `user`.
+    async fn list_followees(
+        &self,
+        user: i32,
+        viewer: Option<i32>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<FollowList, Error>;
+    async fn follow_counts(&self, user: i32, viewer: Option<i32>) -> Result<FollowCounts, Error>;
+    /// Ids of every user followed by `user`, private follows included.
+    async fn followee_ids(&self, user: i32) -> Result<Vec<i32>, Error>;
+}
+
+#[async_trait]
+pub trait ApplicationRepository {
+    /// New applications are pending, unless the question is by the vtuber, and
+    /// only shown to others once approved.
+    async fn apply_question_to_vtuber(&self, qid: i32, uid: i32) -> Result<(), Error>;
+    /// Rejected and hidden applications are kept, so that applying again does
+    /// not undo the review.
+    async fn remove_question_to_vtuber(&self, qid: i32, uid: i32) -> Result<(), Error>;
+    /// Vtubers the question is applied to and approved by.
+    async fn get_question_applied(&self, qid: i32) -> Result<Vec<i32>, Error>;
+    async fn get_application(&self, qid: i32, vtuber: i32) -> Result<Application, Error>;
+    /// Set the status of an application, unpinning it unless approved. The
+    /// first approval adds the application to the feed.
+    async fn review_application(
+        &self,
+        qid: i32,
+        vtuber: i32,
+        status: ApplicationStatus,
+        pinned: bool,
+    ) -> Result<(), Error>;
+    /// Published questions applied to `vtuber`, only those in `status` if
+    /// given, pinned first and then newest first.
+    async fn list_applications(
+        &self,
+        vtuber: i32,
+        status: Option<ApplicationStatus>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<ApplicationList, Error>;
+}
+
+/// Events are recorded by the other repositories as questions are published
+/// and applied, this one only reads them.
+#[async_trait]
+pub trait EventRepository {
+    /// Events of the users followed by `user` and of questions applied to the
+    /// vtubers followed by `user`, with ids below `before`, newest first.
+    async fn feed(&self, user: i32, before: Option<i64>, limit: i64) -> Result<Feed, Error>;
+}
+
+/// Notifications are sent by the other repositories as questions are voted
+/// on and applied and users are followed, this one reads them.
+#[async_trait]
+pub trait NotificationRepository {
+    /// Notifications of `user` with ids below `before`, newest first.
+    async fn list_notifications(
+        &self,
+        user: i32,
+        before: Option<i64>,
+        limit: i64,
+        unread_only: bool,
+    ) -> Result<NotificationList, Error>;
+    async fn unread_notifications(&self, user: i32) -> Result<i64, Error>;
+    /// Mark the notifications of `user` given by `ids` as read, or all of
+    /// them. Ids of notifications of other users are ignored.
+    async fn mark_notifications_read(&self, user: i32, ids: Option<&[i64]>) -> Result<(), Error>;
+}
+
+#[async_trait]
+pub trait QuizRepository {
+    async fn create_quiz_set(&self, owner: i32, req: &QuizSetRequest) -> Result<i32, Error>;
+    async fn get_quiz_set(&self, id: i32) -> Result<QuizSet, Error>;
+    /// Replace a quiz set, bumping its version.
+    async fn update_quiz_set(&self, id: i32, req: &QuizSetRequest) -> Result<(), Error>;
+    /// Delete a quiz set along with its attempts.
+    async fn delete_quiz_set(&self, id: i32) -> Result<(), Error>;
+    /// Quiz sets about `vtuber`, or owned by `owner`, with drafts only if
+    /// `drafts`. Official quiz sets first, then most recently updated first.
+    async fn list_quiz_sets(
+        &self,
+        vtuber: Option<i32>,
+        owner: Option<i32>,
+        drafts: bool,
+        offset: i64,
+        limit: i64,
+    ) -> Result<QuizSetList, Error>;
+    /// Whether a published quiz set asks the question.
+    async fn question_in_quiz_set(&self, qid: i32) -> Result<bool, Error>;
+    /// Start an attempt of a quiz set as of `version` and `pass_mark`, asking
+    /// `questions`.
+    async fn start_attempt(
+        &self,
+        quiz_set: i32,
+        version: i32,
+        pass_mark: i32,
+        taker: i32,
+        questions: &[i32],
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Attempt, Error>;
+    async fn get_attempt(&self, id: i64) -> Result<Attempt, Error>;
+    /// Record the answers of an unfinished attempt, given whether each was
+    /// correct, passing it if at least its pass mark were. Deliveries of the
+    /// attempt are queued to the webhooks of the vtuber of the quiz set, and
+    /// of its leaderboard if the attempt changed it while it is published. A
+    /// `shared` result of a published quiz set is added to the feed.
+    async fn finish_attempt(
+        &self,
+        id: i64,
+        correct: &[bool],
+        shared: bool,
+    ) -> Result<Attempt, Error>;
+    /// The best takers of a quiz set, see [`Leaderboard`].
+    async fn leaderboard(&self, quiz_set: i32) -> Result<Leaderboard, Error>;
+}
+
+/// Webhooks of vtubers. Deliveries are queued by the other repositories as
+/// questions are applied, and sent by [`crate::webhook`].
+#[async_trait]
+pub trait WebhookRepository {
+    async fn create_webhook(&self, owner: i32, url: &str, secret: &str) -> Result<Webhook, Error>;
+    async fn list_webhooks(&self, owner: i32) -> Result<Vec<Webhook>, Error>;
+    /// Fails with [`Error::NotFound`] unless the webhook belongs to `owner`.
+    async fn delete_webhook(&self, owner: i32, id: i32) -> Result<(), Error>;
+    /// Deliveries to a webhook of `owner` with ids below `before`, newest
+    /// first.
+    async fn list_deliveries(
+        &self,
+        owner: i32,
+        webhook: i32,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<DeliveryList, Error>;
+}
+
+pub trait Storage:
+    UserRepository
+    + BilibiliRepository
+    + LoginAttemptRepository
+    + RateLimitRepository
+    + QuestionRepository
+    + VoteRepository
+    + FollowRepository
+    + ApplicationRepository
+    + EventRepository
+    + NotificationRepository
+    + WebhookRepository
+    + QuizRepository
+    + Send
+    + Sync
+{
+}
+
+impl<T> Storage for T where
+    T: UserRepository
+        + BilibiliRepository
+        + LoginAttemptRepository
+        + RateLimitRepository
+        + QuestionRepository
+        + VoteRepository
+        + FollowRepository
+        + ApplicationRepository
+        + EventRepository
+        + NotificationRepository
+        + WebhookRepository
+        + QuizRepository
+        + Send
+        + Sync
+{
+}
@@ -0,0 +1,343 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use vtuber_quiz_commons::models::*;
+
+use super::*;
+use crate::{config, db};
+
+pub struct PgStorage {
+    pool: PgPool,
+    argon2: config::Argon2,
+}
+
+impl PgStorage {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            argon2: Default::default(),
+        }
+    }
+
+    /// Hash new passwords with `argon2` instead of the defaults.
+    pub fn with_argon2(mut self, argon2: config::Argon2) -> Self {
+        self.argon2 = argon2;
+        self
+    }
+}
+
+#[async_trait]
+impl UserRepository for PgStorage {
+    async fn create_user(&self, username: &str, password: &str) -> Result<i32, Error> {
+        db::create_user(&self.pool, username, password, &self.argon2).await
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<User, Error> {
+        db::get_user_by_username(&self.pool, username).await
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<User, Error> {
+        db::get_user_by_id(&self.pool, id).await
+    }
+
+    async fn create_or_replace_challenge(&self, id: i32) -> Result<String, Error> {
+        db::create_or_replace_challenge(&self.pool, id).await
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<User, Error> {
+        db::login(&self.pool, username, password, &self.argon2).await
+    }
+
+    async fn set_password(&self, id: i32, password: &str) -> Result<(), Error> {
+        db::set_password(&self.pool, id, password, &self.argon2).await
+    }
+
+    async fn get_profile(&self, id: i32, viewer: Option<i32>) -> Result<Profile, Error> {
+        db::get_profile(&self.pool, id, viewer).await
+    }
+}
+
+#[async_trait]
+impl BilibiliRepository for PgStorage {
+    async fn get_bilibili(&self, user: i32) -> Result<Bilibili, Error> {
+        db::get_bilibili(&self.pool, user).await
+    }
+
+    async fn bind_bilibili(
+        &self,
+        user: i32,
+        uid: i64,
+        name: &str,
+        avatar: &str,
+    ) -> Result<(), Error> {
+        db::bind_bilibili(&self.pool, user, uid, name, avatar).await
+    }
+
+    async fn create_recovery(&self, user: i32) -> Result<String, Error> {
+        db::create_recovery(&self.pool, user).await
+    }
+
+    async fn get_recovery_code(&self, user: i32) -> Result<String, Error> {
+        db::get_recovery_code(&self.pool, user).await
+    }
+
+    async fn delete_recovery(&self, user: i32) -> Result<(), Error> {
+        db::delete_recovery(&self.pool, user).await
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepository for PgStorage {
+    async fn login_blocked_secs(&self, key: &str) -> Result<Option<u64>, Error> {
+        db::login_blocked_secs(&self.pool, key).await
+    }
+
+    async fn login_failures(&self, key: &str, window_secs: u64) -> Result<u32, Error> {
+        db::login_failures(&self.pool, key, window_secs).await
+    }
+
+    async fn record_login_failure(&self, key: &str, reset_secs: u64) -> Result<u32, Error> {
+        db::record_login_failure(&self.pool, key, reset_secs).await
+    }
+
+    async fn block_login(&self, key: &str, secs: u64) -> Result<(), Error> {
+        db::block_login(&self.pool, key, secs).await
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<(), Error> {
+        db::clear_login_failures(&self.pool, key).await
+    }
+}
+
+#[async_trait]
+impl RateLimitRepository for PgStorage {
+    async fn take_token(&self, key: &str, bucket: Bucket) -> Result<Option<u64>, Error> {
+        db::take_token(&self.pool, key, bucket).await
+    }
+}
+
+#[async_trait]
+impl QuestionRepository for PgStorage {
+    async fn create_question(
+        &self,
+        creator: i32,
+        question: QuestionCreationRequest,
+    ) -> Result<i32, Error> {
+        db::create_question(&self.pool, creator, question).await
+    }
+
+    async fn update_question(&self, question: Question) -> Result<(), Error> {
+        db::update_question(&self.pool, question).await
+    }
+
+    async fn get_question(&self, qid: i32) -> Result<Question, Error> {
+        db::get_question(&self.pool, qid).await
+    }
+
+    async fn delete_question(&self, qid: i32) -> Result<(), Error> {
+        db::delete_question(&self.pool, qid).await
+    }
+}
+
+#[async_trait]
+impl VoteRepository for PgStorage {
+    async fn vote_to_question(&self, uid: i32, qid: i32, action: VoteAction) -> Result<(), Error> {
+        db::vote_to_question(&self.pool, uid, qid, action).await
+    }
+}
+
+#[async_trait]
+impl FollowRepository for PgStorage {
+    async fn follow(&self, from: i32, to: i32, private: bool) -> Result<(), Error> {
+        db::follow(&self.pool, from, to, private).await
+    }
+
+    async fn unfollow(&self, from: i32, to: i32) -> Result<(), Error> {
+        db::unfollow(&self.pool, from, to).await
+    }
+
+    async fn list_followers(
+        &self,
+        user: i32,
+        viewer: Option<i32>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<FollowList, Error> {
+        db::list_followers(&self.pool, user, viewer, offset, limit).await
+    }
+
+    async fn list_followees(
+        &self,
+        user: i32,
+        viewer: Option<i32>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<FollowList, Error> {
+        db::list_followees(&self.pool, user, viewer, offset, limit).await
+    }
+
+    async fn follow_counts(&self, user: i32, viewer: Option<i32>) -> Result<FollowCounts, Error> {
+        db::follow_counts(&self.pool, user, viewer).await
+    }
+
+    async fn followee_ids(&self, user: i32) -> Result<Vec<i32>, Error> {
+        db::followee_ids(&self.pool, user).await
+    }
+}
+
+#[async_trait]
+impl ApplicationRepository for PgStorage {
+    async fn apply_question_to_vtuber(&self, qid: i32, uid: i32) -> Result<(), Error> {
+        db::apply_question_to_vtuber(&self.pool, qid, uid).await
+    }
+
+    async fn remove_question_to_vtuber(&self, qid: i32, uid: i32) -> Result<(), Error> {
+        db::remove_question_to_vtuber(&self.pool, qid, uid).await
+    }
+
+    async fn get_question_applied(&self, qid: i32) -> Result<Vec<i32>, Error> {
+        db::get_question_applied(&self.pool, qid).await
+    }
+
+    async fn get_application(&self, qid: i32, vtuber: i32) -> Result<Application, Error> {
+        db::get_application(&self.pool, qid, vtuber).await
+    }
+
+    async fn review_application(
+        &self,
+        qid: i32,
+        vtuber: i32,
+        status: ApplicationStatus,
+        pinned: bool,
+    ) -> Result<(), Error> {
+        db::review_application(&self.pool, qid, vtuber, status, pinned).await
+    }
+
+    async fn list_applications(
+        &self,
+        vtuber: i32,
+        status: Option<ApplicationStatus>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<ApplicationList, Error> {
+        db::list_vtuber_applications(&self.pool, vtuber, status, offset, limit).await
+    }
+}
+
+#[async_trait]
+impl EventRepository for PgStorage {
+    async fn feed(&self, user: i32, before: Option<i64>, limit: i64) -> Result<Feed, Error> {
+        db::feed(&self.pool, user, before, limit).await
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for PgStorage {
+    async fn list_notifications(
+        &self,
+        user: i32,
+        before: Option<i64>,
+        limit: i64,
+        unread_only: bool,
+    ) -> Result<NotificationList, Error> {
+        db::list_notifications(&self.pool, user, before, limit, unread_only).await
+    }
+
+    async fn unread_notifications(&self, user: i32) -> Result<i64, Error> {
+        db::unread_notifications(&self.pool, user).await
+    }
+
+    async fn mark_notifications_read(&self, user: i32, ids: Option<&[i64]>) -> Result<(), Error> {
+        db::mark_notifications_read(&self.pool, user, ids).await
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for PgStorage {
+    async fn create_webhook(&self, owner: i32, url: &str, secret: &str) -> Result<Webhook, Error> {
+        db::create_webhook(&self.pool, owner, url, secret).await
+    }
+
+    async fn list_webhooks(&self, owner: i32) -> Result<Vec<Webhook>, Error> {
+        db::list_webhooks(&self.pool, owner).await
+    }
+
+    async fn delete_webhook(&self, owner: i32, id: i32) -> Result<(), Error> {
+        db::delete_webhook(&self.pool, owner, id).await
+    }
+
+    async fn list_deliveries(
+        &self,
+        owner: i32,
+        webhook: i32,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<DeliveryList, Error> {
+        db::list_deliveries(&self.pool, owner, webhook, before, limit).await
+    }
+}
+
+#[async_trait]
+impl QuizRepository for PgStorage {
+    async fn create_quiz_set(&self, owner: i32, req: &QuizSetRequest) -> Result<i32, Error> {
+        db::create_quiz_set(&self.pool, owner, req).await
+    }
+
+    async fn get_quiz_set(&self, id: i32) -> Result<QuizSet, Error> {
+        db::get_quiz_set(&self.pool, id).await
+    }
+
+    async fn update_quiz_set(&self, id: i32, req: &QuizSetRequest) -> Result<(), Error> {
+        db::update_quiz_set(&self.pool, id, req).await
+    }
+
+    async fn delete_quiz_set(&self, id: i32) -> Result<(), Error> {
+        db::delete_quiz_set(&self.pool, id).await
+    }
+
+    async fn list_quiz_sets(
+        &self,
+        vtuber: Option<i32>,
+        owner: Option<i32>,
+        drafts: bool,
+        offset: i64,
+        limit: i64,
+    ) -> Result<QuizSetList, Error> {
+        db::list_quiz_sets(&self.pool, vtuber, owner, drafts, offset, limit).await
+    }
+
+    async fn question_in_quiz_set(&self, qid: i32) -> Result<bool, Error> {
+        db::question_in_quiz_set(&self.pool, qid).await
+    }
+
+    async fn start_attempt(
+        &self,
+        quiz_set: i32,
+        version: i32,
+        pass_mark: i32,
+        taker: i32,
+        questions: &[i32],
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Attempt, Error> {
+        db::start_attempt(
+            &self.pool, quiz_set, version, pass_mark, taker, questions, deadline,
+        )
+        .await
+    }
+
+    async fn get_attempt(&self, id: i64) -> Result<Attempt, Error> {
+        db::get_attempt(&self.pool, id).await
+    }
+
+    async fn finish_attempt(
+        &self,
+        id: i64,
+        correct: &[bool],
+        shared: bool,
+    ) -> Result<Attempt, Error> {
+        db::finish_attempt(&self.pool, id, correct, shared).await
+    }
+
+    async fn leaderboard(&self, quiz_set: i32) -> Result<Leaderboard, Error> {
+        db::leaderboard(&self.pool, quiz_set).await
+    }
+}
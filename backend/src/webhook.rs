@@ -0,0 +1,294 @@
+//! Events pushed to the webhooks registered by vtubers.
+//!
+//! Deliveries are queued in `webhook_delivery` in the same transaction as the
+//! change they report, and [`run`] sends the due ones. Each delivery is a JSON
+//! `POST` of `{"id", "event", "created", "data"}`, signed with the secret of the
+//! webhook: [`SIGNATURE`] is `sha256=` followed by the hex HMAC-SHA256 of the
+//! [`TIMESTAMP`] header, a `.` and the body. Receivers should check the
+//! signature with [`verify`] and ignore deliveries whose id they have already
+//! seen, as a delivery may be sent more than once.
+//!
+//! Webhooks must resolve to public addresses, both when registered and when
+//! each delivery is sent, unless `webhook.allow_private_hosts` is set to try
+//! out a receiver on the local network. Redirects are not followed.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, RngCore};
+use reqwest::Url;
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::PgPool;
+
+use crate::config;
+use crate::db::{self, DueDelivery};
+use crate::error::Error;
+
+pub const SIGNATURE: &str = "X-VQ-Signature";
+/// Unix seconds when the delivery was signed.
+pub const TIMESTAMP: &str = "X-VQ-Timestamp";
+pub const EVENT: &str = "X-VQ-Event";
+/// Id of the delivery, the same across its attempts.
+pub const DELIVERY: &str = "X-VQ-Delivery";
+
+const MAX_URL_LEN: usize = 2048;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(secret: &str, timestamp: i64, body: &[u8]) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// The [`SIGNATURE`] header of `body` sent at `timestamp`.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let tag = mac(secret, timestamp, body).finalize().into_bytes();
+    format!("sha256={}", hex::encode(tag))
+}
+
+/// Whether `signature` is the [`SIGNATURE`] header of `body` sent at
+/// `timestamp`, compared in constant time.
+pub fn verify(secret: &str, timestamp: i64, body: &[u8], signature: &str) -> bool {
+    let tag = match signature
+        .strip_prefix("sha256=")
+        .and_then(|tag| hex::decode(tag).ok())
+    {
+        Some(tag) => tag,
+        None => return false,
+    };
+    mac(secret, timestamp, body).verify_slice(&tag).is_ok()
+}
+
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; 32];
+    thread_rng().fill_bytes(&mut secret);
+    hex::encode(secret)
+}
+
+/// Webhooks must be absolute `http` or `https` URLs whose host resolves to
+/// public addresses only, see [`is_public`], unless `allow_private` is set.
+pub async fn check_url(url: &str, allow_private: bool) -> Result<(), Error> {
+    if url.len() > MAX_URL_LEN {
+        return Err(Error::invalid("url", "must be at most 2048 characters"));
+    }
+    let parsed = Url::parse(url).map_err(|_| Error::invalid("url", "must be a valid URL"))?;
+    let host = match parsed.host_str() {
+        Some(host) if matches!(parsed.scheme(), "http" | "https") => host,
+        _ => return Err(Error::invalid("url", "must be an http or https URL")),
+    };
+    if allow_private {
+        return Ok(());
+    }
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = match IpAddr::from_str(host) {
+        Ok(addr) => vec![addr],
+        Err(_) => {
+            let port = parsed.port_or_known_default().unwrap_or(80);
+            tokio::net::lookup_host((host, port))
+                .await
+                .map(|addrs| addrs.map(|addr| addr.ip()).collect())
+                .unwrap_or_default()
+        }
+    };
+    if addrs.is_empty() {
+        return Err(Error::invalid("url", "cannot be resolved"));
+    }
+    if !addrs.into_iter().all(is_public) {
+        return Err(Error::invalid("url", "must not point to a private address"));
+    }
+    Ok(())
+}
+
+/// Whether `addr` may be reached by deliveries: loopback, private, link-local,
+/// shared, unspecified, broadcast and multicast addresses may not.
+pub fn is_public(addr: IpAddr) -> bool {
+    match addr.to_canonical() {
+        IpAddr::V4(addr) => {
+            let [a, b, ..] = addr.octets();
+            !(addr.is_loopback()
+                || addr.is_private()
+                || addr.is_link_local()
+                || addr.is_unspecified()
+                || addr.is_broadcast()
+                || addr.is_multicast()
+                || a == 0
+                // shared address space, RFC 6598
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(addr) => {
+            let first = addr.segments()[0];
+            !(addr.is_loopback()
+                || addr.is_unspecified()
+                || addr.is_multicast()
+                // unique local
+                || first & 0xfe00 == 0xfc00
+                // link-local
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// Send the due deliveries every `poll_secs`, until the pool is closed.
+pub async fn run(pool: PgPool, config: config::Webhook) {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("the webhook client has a valid configuration");
+    let poll = Duration::from_secs(config.poll_secs);
+    while !pool.is_closed() {
+        match deliver_due(&pool, &client, &config).await {
+            // a full batch may leave more due
+            Ok(sent) if sent == config.batch as usize => continue,
+            Ok(_) => {}
+            Err(e) => error!("failed to send webhook deliveries: {:?}", e),
+        }
+        tokio::time::sleep(poll).await;
+    }
+}
+
+/// Send a batch of the due deliveries, returning how many were attempted.
+pub async fn deliver_due(
+    pool: &PgPool,
+    client: &reqwest::Client,
+    config: &config::Webhook,
+) -> Result<usize, Error> {
+    // claimed deliveries are left alone by other instances until the attempt
+    // had time to finish
+    let lease = (config.timeout_secs * 2) as f64;
+    let due = db::claim_deliveries(pool, config.batch as i64, lease).await?;
+    let sent = due.len();
+    for delivery in due {
+        let id = delivery.id;
+        let attempts = delivery.attempts as u32 + 1;
+        match send(client, config, &delivery).await {
+            Ok(status) => db::delivery_succeeded(pool, id, status).await?,
+            Err((status, error)) => {
+                let retry = config.retry_secs(attempts);
+                if retry.is_none() {
+                    warn!("giving up webhook delivery {} to {}", id, delivery.url);
+                }
+                db::delivery_failed(pool, id, status, &error, retry).await?
+            }
+        }
+    }
+    Ok(sent)
+}
+
+/// Post `delivery`, returning the status of a successful response, or the
+/// status, if any, and the reason of a failure.
+async fn send(
+    client: &reqwest::Client,
+    config: &config::Webhook,
+    delivery: &DueDelivery,
+) -> Result<i32, (Option<i32>, String)> {
+    // the host may resolve elsewhere since the webhook was registered
+    if check_url(&delivery.url, config.allow_private_hosts)
+        .await
+        .is_err()
+    {
+        let reason = "the host is private or cannot be resolved";
+        return Err((None, reason.to_string()));
+    }
+    let data: serde_json::Value = serde_json::from_str(&delivery.payload)
+        .map_err(|e| (None, format!("invalid payload: {}", e)))?;
+    let event = serde_json::to_value(delivery.event).expect("events serialize to JSON");
+    let body = json!({
+        "id": delivery.id,
+        "event": event,
+        "created": delivery.created.timestamp_millis(),
+        "data": data,
+    })
+    .to_string();
+    let timestamp = chrono::Utc::now().timestamp();
+    let response = client
+        .post(&delivery.url)
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(
+            SIGNATURE,
+            sign(&delivery.secret, timestamp, body.as_bytes()),
+        )
+        .header(TIMESTAMP, timestamp)
+        .header(EVENT, event.as_str().unwrap_or_default())
+        .header(DELIVERY, delivery.id)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16() as i32)
+    } else {
+        Err((Some(status.as_u16() as i32), status.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let signature = sign("secret", 1_700_000_000, b"{}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert!(verify("secret", 1_700_000_000, b"{}", &signature));
+        assert!(!verify("other", 1_700_000_000, b"{}", &signature));
+        assert!(!verify("secret", 1_700_000_001, b"{}", &signature));
+        assert!(!verify("secret", 1_700_000_000, b"[]", &signature));
+        assert!(!verify("secret", 1_700_000_000, b"{}", "sha256=zz"));
+        assert_ne!(generate_secret(), generate_secret());
+    }
+
+    #[test]
+    fn test_is_public() {
+        for addr in ["203.0.113.7", "8.8.8.8", "2001:db8::1", "::ffff:8.8.8.8"].iter() {
+            assert!(is_public(addr.parse().unwrap()), "{}", addr);
+        }
+        for addr in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ]
+        .iter()
+        {
+            assert!(!is_public(addr.parse().unwrap()), "{}", addr);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        assert!(check_url("https://203.0.113.7/hook", false).await.is_ok());
+        assert!(check_url("http://127.0.0.1:8080", false).await.is_err());
+        assert!(check_url("http://169.254.169.254/latest", false)
+            .await
+            .is_err());
+        assert!(check_url("http://10.0.0.1/hook", false).await.is_err());
+        assert!(check_url("http://[::1]:8080", false).await.is_err());
+        assert!(check_url("http://localhost:8080", false).await.is_err());
+        // a receiver on the local network, when allowed
+        assert!(check_url("http://127.0.0.1:8080", true).await.is_ok());
+        assert!(check_url("ftp://203.0.113.7", true).await.is_err());
+        assert!(check_url("example.com/hook", true).await.is_err());
+        assert!(check_url("mailto:someone@example.com", true).await.is_err());
+        let long = format!("https://203.0.113.7/{}", "a".repeat(MAX_URL_LEN));
+        assert!(check_url(&long, true).await.is_err());
+    }
+}
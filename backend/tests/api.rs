@@ -0,0 +1,1362 @@
+//! End-to-end API tests against Postgres.
+//!
+//! Every test creates and migrates its own database on the server given by
+//! `DATABASE_URL`. Captchas are skipped with the `bypass-hcaptcha` feature:
+//!
+//! ```sh
+//! cargo test -p vtuber-quiz-backend --features bypass-hcaptcha --test api
+//! ```
+#![cfg(feature = "bypass-hcaptcha")]
+
+use std::convert::{Infallible, TryFrom};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::cookie::Cookie;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use vtuber_quiz_backend::config::{self, Argon2};
+use vtuber_quiz_backend::db;
+use vtuber_quiz_backend::error::Error;
+use vtuber_quiz_backend::live::{self, Broker};
+use vtuber_quiz_backend::rate_limit::Bucket;
+use vtuber_quiz_backend::storage::PgStorage;
+use vtuber_quiz_backend::webhook;
+use vtuber_quiz_commons::models::*;
+
+use common::{call, TestDb, TestOptions};
+
+#[macro_use]
+mod common;
+
+async fn register<S, B>(app: &S, username: &str) -> (i32, Cookie<'static>)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/user")
+        .set_json(json!({"username": username, "password": "password1"}))
+        .to_request();
+    let resp = call(app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    (
+        resp.body["id"].as_i64().unwrap() as i32,
+        resp.cookie.unwrap(),
+    )
+}
+
+async fn create_question<S, B>(app: &S, cookie: &Cookie<'static>, draft: bool) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/api/question")
+        .cookie(cookie.clone())
+        .set_json(json!({
+            "content": {"description": "1+1=2", "is_true": true},
+            "audiences": ["fan", "passenger"],
+            "draft": draft
+        }))
+        .to_request();
+    let resp = call(app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    resp.body
+}
+
+#[actix_web::test]
+async fn test_register_and_login() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+
+    let (id, _) = register(&app, "alice").await;
+
+    let req = TestRequest::post()
+        .uri("/api/user")
+        .set_json(json!({"username": "alice", "password": "other-password"}))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::CONFLICT);
+    assert_eq!(resp.body["code"], 430001);
+
+    let req = TestRequest::post()
+        .uri("/api/user")
+        .set_json(json!({"username": "a".repeat(21), "password": "password1"}))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "username");
+
+    let req = TestRequest::post()
+        .uri("/api/user")
+        .set_json(json!({"username": "self", "password": "self"}))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = resp.body["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| d["field"].as_str().unwrap())
+        .collect();
+    assert!(fields.contains(&"username"));
+    assert!(fields.contains(&"password"));
+
+    let req = TestRequest::post()
+        .uri("/api/user/alice/session")
+        .set_json(json!({"password": "wrong"}))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.body["code"], 420000);
+
+    let req = TestRequest::post()
+        .uri("/api/user/nobody/session")
+        .set_json(json!({"password": "password1"}))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.body["code"], 420000);
+
+    let req = TestRequest::post()
+        .uri("/api/user/alice/session")
+        .set_json(json!({"password": "password1"}))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["id"], id);
+
+    let req = TestRequest::get()
+        .uri("/api/user/self")
+        .cookie(resp.cookie.unwrap())
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["username"], "alice");
+
+    let req = TestRequest::get().uri("/api/user/self").to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.body["code"], 420003);
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_rehash_on_login() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let weak = Argon2 {
+        memory_cost: 64,
+        time_cost: 1,
+        parallelism: 1,
+    };
+    let id = db::create_user(&db.pool, "alice", "password1", &weak)
+        .await
+        .unwrap();
+    let hash = db::get_user_by_id(&db.pool, id).await.unwrap().password;
+    assert!(hash.contains("m=64,t=1,p=1"));
+    assert!(db::needs_rehash(&hash, &Argon2::default()));
+    assert!(!db::needs_rehash(&hash, &weak));
+
+    let req = TestRequest::post()
+        .uri("/api/user/alice/session")
+        .set_json(json!({"password": "password1"}))
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::OK);
+    let hash = db::get_user_by_id(&db.pool, id).await.unwrap().password;
+    assert!(!db::needs_rehash(&hash, &Argon2::default()));
+    assert!(db::verify_password(&hash, b"password1").unwrap());
+    assert!(db::verify_password("not a hash", b"password1").is_err());
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_login_throttle() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    register(&app, "alice").await;
+    register(&app, "bob").await;
+    let login = |username: &str, password: &str| {
+        TestRequest::post()
+            .uri(&format!("/api/user/{}/session", username))
+            .set_json(json!({ "password": password }))
+            .to_request()
+    };
+
+    // the free attempts, then one more which blocks alice for a second
+    for _ in 0..6 {
+        let resp = call(&app, login("alice", "wrong-password")).await;
+        assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+    }
+    let resp = call(&app, login("alice", "password1")).await;
+    assert_eq!(resp.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.body["code"], 420004);
+    assert_eq!(resp.body["details"]["retry_after"], 1);
+    // the client address is still below its own limit
+    let resp = call(&app, login("bob", "password1")).await;
+    assert_eq!(resp.status, StatusCode::OK);
+
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let resp = call(&app, login("alice", "password1")).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(
+        db::login_blocked_secs(&db.pool, "user:alice")
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        db::record_login_failure(&db.pool, "user:alice", 3600)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        db::record_login_failure(&db.pool, "ip:unknown", 3600)
+            .await
+            .unwrap(),
+        7
+    );
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_question_crud() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (_, bob_cookie) = register(&app, "bob").await;
+
+    let question = create_question(&app, &alice_cookie, true).await;
+    assert_eq!(question["creator"], alice);
+    assert_eq!(question["type"], "true_false");
+    let qid = question["id"].as_i64().unwrap();
+    let uri = format!("/api/question/{}", qid);
+
+    let req = TestRequest::get().uri(&uri).to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["description"], "1+1=2");
+
+    let mut update = question.clone();
+    update["draft"] = json!(false);
+    update["description"] = json!("1+1=3");
+    update["answer"] = json!([1]);
+    let req = TestRequest::put()
+        .uri(&uri)
+        .cookie(bob_cookie.clone())
+        .set_json(&update)
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::FORBIDDEN);
+    assert_eq!(resp.body["code"], 420001);
+
+    let req = TestRequest::put()
+        .uri(&format!("/api/question/{}", qid + 1))
+        .cookie(alice_cookie.clone())
+        .set_json(&update)
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["code"], 450000);
+    assert_eq!(resp.body["details"][0]["field"], "id");
+
+    let req = TestRequest::put()
+        .uri(&uri)
+        .cookie(alice_cookie.clone())
+        .set_json(json!({"id": qid}))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["code"], 450000);
+    assert_eq!(resp.body["details"][0]["field"], "body");
+
+    let req = TestRequest::put()
+        .uri(&uri)
+        .cookie(alice_cookie.clone())
+        .set_json(&update)
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["description"], "1+1=3");
+    assert_eq!(resp.body["draft"], false);
+
+    let req = TestRequest::delete()
+        .uri(&uri)
+        .cookie(bob_cookie)
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::FORBIDDEN);
+
+    let req = TestRequest::delete()
+        .uri(&uri)
+        .cookie(alice_cookie)
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+
+    let req = TestRequest::get().uri(&uri).to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NOT_FOUND);
+
+    let req = TestRequest::get().uri("/api/question/0").to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::NOT_FOUND);
+    assert_eq!(resp.body["code"], 440000);
+
+    let req = TestRequest::get().uri("/api/question/abc").to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["code"], 450001);
+
+    let req = TestRequest::get().uri("/api/no/such/route").to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::NOT_FOUND);
+    assert_eq!(resp.body["code"], 440000);
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_vote() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (bob, bob_cookie) = register(&app, "bob").await;
+    let qid = create_question(&app, &alice_cookie, false).await["id"].clone();
+    let draft = create_question(&app, &alice_cookie, true).await["id"].clone();
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/vote/{}/up_vote", qid))
+        .cookie(alice_cookie)
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::BAD_REQUEST);
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/vote/{}/up_vote", draft))
+        .cookie(bob_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NOT_FOUND);
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/vote/{}/like", qid))
+        .cookie(bob_cookie.clone())
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["code"], 450001);
+    assert_eq!(resp.body["details"][0]["field"], "action");
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/vote/{}/up_vote", qid))
+        .cookie(bob_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/vote/{}/down_vote", qid))
+        .cookie(bob_cookie.clone())
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::CONFLICT);
+    assert_eq!(resp.body["code"], 430002);
+
+    db::set_user_blocked(&db.pool, bob, true).await.unwrap();
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/vote/{}/up_vote", qid))
+        .cookie(bob_cookie)
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::FORBIDDEN);
+    assert_eq!(resp.body["code"], 420002);
+
+    let res = db::vote_to_question(&db.pool, alice, 0, VoteAction::UpVote).await;
+    assert!(matches!(res, Err(Error::NotFound)));
+
+    let votes = db::list_votes(&db.pool).await.unwrap();
+    assert_eq!(votes.len(), 1);
+    assert_eq!(votes[0].action, VoteAction::UpVote);
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_not_null_violation() {
+    let db = TestDb::new().await;
+
+    let e = sqlx::query("insert into question (creator) values (null)")
+        .execute(&db.pool)
+        .await
+        .unwrap_err();
+    let pg = db::PgError::try_from(e).unwrap();
+    assert!(matches!(pg, db::PgError::NotNullViolation(_)));
+    assert_eq!(pg.column(), Some("creator"));
+    match Error::from(pg) {
+        Error::Validation(errors) => assert_eq!(errors[0].field, "creator"),
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_apply_to_vtuber() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (_, alice_cookie) = register(&app, "alice").await;
+    let (bob, bob_cookie) = register(&app, "bob").await;
+    let (vtuber, _) = register(&app, "vtuber").await;
+    db::set_user_role(&db.pool, vtuber, UserRole::Vtuber)
+        .await
+        .unwrap();
+    let qid = create_question(&app, &alice_cookie, false).await["id"].clone();
+    let apply = |uid: i32| format!("/api/question/{}/apply/{}", qid, uid);
+
+    let req = TestRequest::post()
+        .uri(&apply(bob))
+        .cookie(alice_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::BAD_REQUEST);
+
+    let req = TestRequest::post()
+        .uri(&apply(vtuber))
+        .cookie(bob_cookie)
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::FORBIDDEN);
+
+    let req = TestRequest::post()
+        .uri(&apply(vtuber))
+        .cookie(alice_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+
+    // pending applications are not shown until the vtuber approves them
+    let applied = || {
+        TestRequest::get()
+            .uri(&format!("/api/question/{}/apply", qid))
+            .to_request()
+    };
+    assert_eq!(call(&app, applied()).await.body, json!([]));
+    let id = qid.as_i64().unwrap() as i32;
+    db::review_application(&db.pool, id, vtuber, ApplicationStatus::Approved, false)
+        .await
+        .unwrap();
+    assert_eq!(call(&app, applied()).await.body, json!([vtuber]));
+
+    let req = TestRequest::delete()
+        .uri(&apply(vtuber))
+        .cookie(alice_cookie)
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+
+    let req = TestRequest::get()
+        .uri(&format!("/api/question/{}/apply", qid))
+        .to_request();
+    assert_eq!(call(&app, req).await.body, json!([]));
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_follow() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (bob, _) = register(&app, "bob").await;
+    let follow = format!("/api/user/by-id/{}/follow", bob);
+
+    let req = TestRequest::post()
+        .uri(&follow)
+        .set_json(json!({}))
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::UNAUTHORIZED);
+
+    for _ in 0..2 {
+        let req = TestRequest::post()
+            .uri(&follow)
+            .cookie(alice_cookie.clone())
+            .set_json(json!({"private": true}))
+            .to_request();
+        assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+    }
+    let following = db::list_followings(&db.pool).await.unwrap();
+    assert_eq!(following.len(), 1);
+    assert_eq!(following[0].follower, alice);
+    assert_eq!(following[0].followee, bob);
+    assert!(following[0].private);
+
+    let (carol, carol_cookie) = register(&app, "carol").await;
+    let req = TestRequest::post()
+        .uri(&follow)
+        .cookie(carol_cookie)
+        .set_json(json!({}))
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+    let req = TestRequest::get()
+        .uri(&format!("/api/user/by-id/{}/followers?limit=10", bob))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.body["total"], 1);
+    assert_eq!(resp.body["follows"][0]["id"], carol);
+    let list = db::list_followers(&db.pool, bob, Some(alice), 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(list.total, 2);
+    assert_eq!(list.follows[0].id, carol);
+    assert_eq!(list.follows[1].id, alice);
+    let list = db::list_followees(&db.pool, alice, Some(bob), 0, 10)
+        .await
+        .unwrap();
+    assert_eq!(list.total, 0);
+    let counts = db::follow_counts(&db.pool, bob, None).await.unwrap();
+    assert_eq!((counts.followers, counts.followees), (1, 0));
+    db::unfollow(&db.pool, carol, bob).await.unwrap();
+
+    let req = TestRequest::delete()
+        .uri(&follow)
+        .cookie(alice_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+    assert!(db::list_followings(&db.pool).await.unwrap().is_empty());
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/by-id/{}/follow", alice))
+        .cookie(alice_cookie)
+        .set_json(json!({}))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["code"], 450001);
+
+    let res = db::follow(&db.pool, alice, 0, false).await;
+    assert!(matches!(res, Err(Error::NotFound)));
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_get_profile() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (vtuber, _) = register(&app, "vtuber").await;
+    db::set_user_role(&db.pool, vtuber, UserRole::Vtuber)
+        .await
+        .unwrap();
+    db::bind_bilibili(&db.pool, vtuber, 546195, "vtuber_official", "face.jpg")
+        .await
+        .unwrap();
+    let qid = create_question(&app, &alice_cookie, false).await["id"]
+        .as_i64()
+        .unwrap() as i32;
+    let draft = create_question(&app, &alice_cookie, true).await["id"]
+        .as_i64()
+        .unwrap() as i32;
+    db::apply_question_to_vtuber(&db.pool, qid, vtuber)
+        .await
+        .unwrap();
+    db::apply_question_to_vtuber(&db.pool, draft, vtuber)
+        .await
+        .unwrap();
+    db::follow(&db.pool, alice, vtuber, true).await.unwrap();
+
+    // pending applications are not counted
+    let profile = db::get_profile(&db.pool, vtuber, None).await.unwrap();
+    assert_eq!(profile.applied_questions, Some(0));
+    for qid in [qid, draft] {
+        db::review_application(&db.pool, qid, vtuber, ApplicationStatus::Approved, false)
+            .await
+            .unwrap();
+    }
+
+    let req = TestRequest::get().uri("/api/user/alice").to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["id"], alice);
+    assert_eq!(resp.body["questions"], 1);
+    assert_eq!(resp.body["followees"], 0);
+    assert_eq!(resp.body["applied_questions"], Value::Null);
+
+    let req = TestRequest::get()
+        .uri(&format!("/api/user/by-id/{}", vtuber))
+        .cookie(alice_cookie)
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.body["role"], "vtuber");
+    assert_eq!(resp.body["bilibili_name"], "vtuber_official");
+    assert_eq!(resp.body["applied_questions"], 1);
+    assert_eq!(resp.body["followers"], 1);
+
+    let profile = db::get_profile(&db.pool, vtuber, None).await.unwrap();
+    assert_eq!(profile.follow_counts.followers, 0);
+    let res = db::get_profile(&db.pool, 0, None).await;
+    assert!(matches!(res, Err(Error::NotFound)));
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_feed() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (bob, bob_cookie) = register(&app, "bob").await;
+    let (vtuber, _) = register(&app, "vtuber").await;
+    db::set_user_role(&db.pool, vtuber, UserRole::Vtuber)
+        .await
+        .unwrap();
+    db::follow(&db.pool, alice, bob, true).await.unwrap();
+    let published = create_question(&app, &bob_cookie, false).await["id"]
+        .as_i64()
+        .unwrap() as i32;
+    let draft = create_question(&app, &bob_cookie, true).await["id"]
+        .as_i64()
+        .unwrap() as i32;
+    db::apply_question_to_vtuber(&db.pool, published, vtuber)
+        .await
+        .unwrap();
+    db::apply_question_to_vtuber(&db.pool, published, vtuber)
+        .await
+        .unwrap();
+    // pending applications stay out of the feed, approving twice adds one
+    let events = db::list_events(&db.pool).await.unwrap();
+    assert_eq!(events.len(), 1);
+    for _ in 0..2 {
+        db::review_application(
+            &db.pool,
+            published,
+            vtuber,
+            ApplicationStatus::Approved,
+            false,
+        )
+        .await
+        .unwrap();
+    }
+    let events = db::list_events(&db.pool).await.unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1].kind, EventKind::QuestionApplied);
+    assert_eq!(events[1].vtuber_name.as_deref(), Some("vtuber"));
+
+    let mut question = db::get_question(&db.pool, draft).await.unwrap();
+    question.draft = false;
+    db::update_question(&db.pool, question.clone())
+        .await
+        .unwrap();
+    db::update_question(&db.pool, question).await.unwrap();
+    let req = TestRequest::get()
+        .uri("/api/user/self/feed?limit=2")
+        .cookie(alice_cookie.clone())
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["events"][0]["question"], draft);
+    assert_eq!(resp.body["events"][1]["kind"], "question_applied");
+    let next = resp.body["next"].as_i64().unwrap();
+    let feed = db::feed(&db.pool, alice, Some(next), 20).await.unwrap();
+    assert_eq!(feed.events.len(), 1);
+    assert_eq!(feed.events[0].question, Some(published));
+    assert_eq!(feed.next, None);
+
+    db::remove_question_to_vtuber(&db.pool, published, vtuber)
+        .await
+        .unwrap();
+    db::delete_question(&db.pool, draft).await.unwrap();
+    let feed = db::feed(&db.pool, alice, None, 20).await.unwrap();
+    assert_eq!(feed.events.len(), 1);
+    assert_eq!(feed.events[0].kind, EventKind::QuestionPublished);
+    assert!(db::feed(&db.pool, bob, None, 20)
+        .await
+        .unwrap()
+        .events
+        .is_empty());
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_notifications() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (bob, bob_cookie) = register(&app, "bob").await;
+    let (vtuber, _) = register(&app, "vtuber").await;
+    db::set_user_role(&db.pool, vtuber, UserRole::Vtuber)
+        .await
+        .unwrap();
+    let qid = create_question(&app, &alice_cookie, false).await["id"]
+        .as_i64()
+        .unwrap() as i32;
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/vote/{}/up_vote", qid))
+        .cookie(bob_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+    let req = TestRequest::post()
+        .uri(&format!("/api/user/by-id/{}/follow", alice))
+        .cookie(bob_cookie)
+        .set_json(json!({}))
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+    db::follow(&db.pool, vtuber, alice, true).await.unwrap();
+    db::apply_question_to_vtuber(&db.pool, qid, vtuber)
+        .await
+        .unwrap();
+    db::apply_question_to_vtuber(&db.pool, qid, vtuber)
+        .await
+        .unwrap();
+    db::notify_moderation(&db.pool, alice, NotificationKind::QuestionDeleted, qid)
+        .await
+        .unwrap();
+
+    let req = TestRequest::get()
+        .uri("/api/user/self/notifications")
+        .cookie(alice_cookie.clone())
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    let notifications = resp.body["notifications"].as_array().unwrap();
+    assert_eq!(notifications.len(), 3);
+    assert_eq!(notifications[0]["kind"], "question_deleted");
+    assert_eq!(notifications[0]["actor"], Value::Null);
+    assert_eq!(notifications[1]["kind"], "followed");
+    assert_eq!(notifications[1]["actor"], bob);
+    assert_eq!(notifications[2]["vote"], "up_vote");
+    let list = db::list_notifications(&db.pool, vtuber, None, 20, false)
+        .await
+        .unwrap();
+    assert_eq!(list.notifications.len(), 1);
+    assert_eq!(list.notifications[0].kind, NotificationKind::Applied);
+
+    let ids = [notifications[0]["id"].as_i64().unwrap()];
+    db::mark_notifications_read(&db.pool, alice, Some(&ids))
+        .await
+        .unwrap();
+    db::mark_notifications_read(&db.pool, vtuber, Some(&ids))
+        .await
+        .unwrap();
+    let req = TestRequest::get()
+        .uri("/api/user/self/notifications/unread")
+        .cookie(alice_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.body, json!({"unread": 2}));
+    let req = TestRequest::post()
+        .uri("/api/user/self/notifications/read")
+        .cookie(alice_cookie)
+        .set_json(json!({}))
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+    assert_eq!(db::unread_notifications(&db.pool, alice).await.unwrap(), 0);
+    assert_eq!(db::unread_notifications(&db.pool, vtuber).await.unwrap(), 1);
+
+    db.close().await;
+}
+
+/// Next server-sent event of a live stream.
+async fn next_event<S>(stream: &mut S) -> String
+where
+    S: Stream<Item = Result<Bytes, Infallible>> + Unpin,
+{
+    let next = actix_web::rt::time::timeout(Duration::from_secs(5), stream.next());
+    let bytes = next
+        .await
+        .expect("no event within 5 seconds")
+        .unwrap()
+        .unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn test_live_listen() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (bob, bob_cookie) = register(&app, "bob").await;
+    let broker = Broker::default();
+    let listener = actix_web::rt::spawn(live::listen(db.pool.clone(), broker.clone()));
+    let stream = broker.stream(alice, [bob].iter().copied().collect());
+    futures::pin_mut!(stream);
+    // give the listener time to LISTEN
+    actix_web::rt::time::sleep(Duration::from_millis(500)).await;
+
+    // alice follows bob, so she gets his events but not those of her own
+    create_question(&app, &alice_cookie, false).await;
+    db::follow(&db.pool, bob, alice, false).await.unwrap();
+    let qid = create_question(&app, &bob_cookie, false).await["id"].clone();
+
+    let chunk = next_event(&mut stream).await;
+    assert!(chunk.starts_with("event: notification\n"), "{}", chunk);
+    assert!(chunk.contains("\"kind\":\"followed\""), "{}", chunk);
+    assert!(chunk.contains(&format!("\"actor\":{}", bob)), "{}", chunk);
+    let chunk = next_event(&mut stream).await;
+    assert!(chunk.starts_with("event: event\n"), "{}", chunk);
+    assert!(
+        chunk.contains(&format!("\"question\":{}", qid)),
+        "{}",
+        chunk
+    );
+
+    listener.abort();
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_webhook_delivery() {
+    let db = TestDb::new().await;
+    // the receiver below listens on the loopback address
+    let config = config::Webhook {
+        max_attempts: 2,
+        backoff_base_secs: 1,
+        allow_private_hosts: true,
+        ..Default::default()
+    };
+    let app = init_app!(
+        Arc::new(PgStorage::new(db.pool.clone())),
+        TestOptions {
+            webhook: config.clone(),
+            ..Default::default()
+        }
+    );
+    let (_, alice_cookie) = register(&app, "alice").await;
+    let (vtuber, vtuber_cookie) = register(&app, "vtuber").await;
+    db::set_user_role(&db.pool, vtuber, UserRole::Vtuber)
+        .await
+        .unwrap();
+
+    // a receiver taking deliveries on /ok and failing them on /fail
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let server = {
+        let received = received.clone();
+        actix_web::HttpServer::new(move || {
+            let received = received.clone();
+            actix_web::App::new().route(
+                "/{path}",
+                actix_web::web::post().to(
+                    move |path: actix_web::web::Path<String>,
+                          req: actix_web::HttpRequest,
+                          body: Bytes| {
+                        let received = received.clone();
+                        async move {
+                            let header = |name| {
+                                req.headers()
+                                    .get(name)
+                                    .unwrap()
+                                    .to_str()
+                                    .unwrap()
+                                    .to_string()
+                            };
+                            received.lock().unwrap().push((
+                                header(webhook::TIMESTAMP),
+                                header(webhook::SIGNATURE),
+                                body,
+                            ));
+                            if path.as_str() == "ok" {
+                                actix_web::HttpResponse::NoContent().finish()
+                            } else {
+                                actix_web::HttpResponse::InternalServerError().finish()
+                            }
+                        }
+                    },
+                ),
+            )
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap()
+    };
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+
+    let mut webhooks = Vec::new();
+    for path in ["ok", "fail"].iter() {
+        let req = TestRequest::post()
+            .uri("/api/user/self/webhooks")
+            .cookie(vtuber_cookie.clone())
+            .set_json(json!({ "url": format!("http://{}/{}", addr, path) }))
+            .to_request();
+        let resp = call(&app, req).await;
+        assert_eq!(resp.status, StatusCode::OK);
+        webhooks.push(resp.body);
+    }
+    let qid = create_question(&app, &alice_cookie, false).await["id"]
+        .as_i64()
+        .unwrap() as i32;
+    let req = TestRequest::post()
+        .uri(&format!("/api/question/{}/apply/{}", qid, vtuber))
+        .cookie(alice_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+
+    // nothing is sent until the application is approved
+    let client = reqwest::Client::new();
+    let sent = webhook::deliver_due(&db.pool, &client, &config)
+        .await
+        .unwrap();
+    assert_eq!(sent, 0);
+    for _ in 0..2 {
+        db::review_application(&db.pool, qid, vtuber, ApplicationStatus::Approved, false)
+            .await
+            .unwrap();
+    }
+    let sent = webhook::deliver_due(&db.pool, &client, &config)
+        .await
+        .unwrap();
+    assert_eq!(sent, 2);
+    {
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let secret = webhooks[0]["secret"].as_str().unwrap();
+        let (timestamp, signature, body) = received
+            .iter()
+            .find(|(timestamp, signature, body)| {
+                webhook::verify(secret, timestamp.parse().unwrap(), body, signature)
+            })
+            .expect("a delivery signed with the secret of the first webhook");
+        assert!(!webhook::verify(
+            "other",
+            timestamp.parse().unwrap(),
+            body,
+            signature
+        ));
+        let body: Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(body["event"], "question_applied");
+        assert_eq!(body["data"]["question"], qid);
+        assert_eq!(body["data"]["vtuber"], vtuber);
+    }
+    let deliveries = |webhook: &Value| {
+        TestRequest::get()
+            .uri(&format!(
+                "/api/user/self/webhooks/{}/deliveries",
+                webhook["id"]
+            ))
+            .cookie(vtuber_cookie.clone())
+            .to_request()
+    };
+    let resp = call(&app, deliveries(&webhooks[0])).await;
+    let delivery = &resp.body["deliveries"][0];
+    assert_eq!(delivery["status"], "delivered");
+    assert_eq!(delivery["attempts"], 1);
+    assert_eq!(delivery["last_status"], 204);
+    assert!(delivery["delivered"].is_i64());
+    let resp = call(&app, deliveries(&webhooks[1])).await;
+    let delivery = &resp.body["deliveries"][0];
+    assert_eq!(delivery["status"], "pending");
+    assert_eq!(delivery["last_status"], 500);
+
+    // retried after the backoff, then given up
+    let sent = webhook::deliver_due(&db.pool, &client, &config)
+        .await
+        .unwrap();
+    assert_eq!(sent, 0);
+    actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+    let sent = webhook::deliver_due(&db.pool, &client, &config)
+        .await
+        .unwrap();
+    assert_eq!(sent, 1);
+    let resp = call(&app, deliveries(&webhooks[1])).await;
+    let delivery = &resp.body["deliveries"][0];
+    assert_eq!(delivery["status"], "failed");
+    assert_eq!(delivery["attempts"], 2);
+    assert_eq!(received.lock().unwrap().len(), 3);
+
+    // nothing is sent to the loopback address unless private hosts are allowed
+    let qid = create_question(&app, &alice_cookie, false).await["id"]
+        .as_i64()
+        .unwrap() as i32;
+    let req = TestRequest::post()
+        .uri(&format!("/api/question/{}/apply/{}", qid, vtuber))
+        .cookie(alice_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+    db::review_application(&db.pool, qid, vtuber, ApplicationStatus::Approved, false)
+        .await
+        .unwrap();
+    let strict = config::Webhook {
+        allow_private_hosts: false,
+        ..config.clone()
+    };
+    let sent = webhook::deliver_due(&db.pool, &client, &strict)
+        .await
+        .unwrap();
+    assert_eq!(sent, 2);
+    assert_eq!(received.lock().unwrap().len(), 3);
+    let resp = call(&app, deliveries(&webhooks[0])).await;
+    let delivery = &resp.body["deliveries"][0];
+    assert_eq!(delivery["status"], "pending");
+    assert_eq!(delivery["last_status"], Value::Null);
+    assert!(delivery["last_error"].as_str().unwrap().contains("private"));
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_review_applications() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (vtuber, vtuber_cookie) = register(&app, "vtuber").await;
+    db::set_user_role(&db.pool, vtuber, UserRole::Vtuber)
+        .await
+        .unwrap();
+    db::follow(&db.pool, alice, vtuber, false).await.unwrap();
+    let mut questions = Vec::new();
+    for _ in 0..2 {
+        let qid = create_question(&app, &alice_cookie, false).await["id"]
+            .as_i64()
+            .unwrap() as i32;
+        db::apply_question_to_vtuber(&db.pool, qid, vtuber)
+            .await
+            .unwrap();
+        questions.push(qid);
+    }
+    let own = create_question(&app, &vtuber_cookie, false).await["id"]
+        .as_i64()
+        .unwrap() as i32;
+    db::apply_question_to_vtuber(&db.pool, own, vtuber)
+        .await
+        .unwrap();
+    let review = |qid: i32, body: Value| {
+        TestRequest::put()
+            .uri(&format!("/api/user/self/applications/{}", qid))
+            .cookie(vtuber_cookie.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    let req = TestRequest::get()
+        .uri("/api/user/self/applications?status=pending")
+        .cookie(vtuber_cookie.clone())
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["total"], 2);
+    assert_eq!(resp.body["applications"][0]["question"], questions[1]);
+    assert_eq!(resp.body["applications"][0]["status"], "pending");
+
+    let resp = call(&app, review(questions[1], json!({ "pinned": true }))).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    let resp = call(
+        &app,
+        review(
+            questions[0],
+            json!({ "status": "approved", "pinned": true }),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::NO_CONTENT);
+    let resp = call(&app, review(questions[1], json!({ "status": "rejected" }))).await;
+    assert_eq!(resp.status, StatusCode::NO_CONTENT);
+
+    let req = TestRequest::get()
+        .uri(&format!("/api/user/by-id/{}/applications", vtuber))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.body["total"], 2);
+    assert_eq!(resp.body["applications"][0]["question"], questions[0]);
+    assert_eq!(resp.body["applications"][0]["pinned"], true);
+    assert_eq!(resp.body["applications"][1]["question"], own);
+    assert_eq!(resp.body["applications"][1]["creator_name"], "vtuber");
+
+    // rejected applications leave the question, the profile and the feed
+    assert!(db::get_question_applied(&db.pool, questions[1])
+        .await
+        .unwrap()
+        .is_empty());
+    let profile = db::get_profile(&db.pool, vtuber, None).await.unwrap();
+    assert_eq!(profile.applied_questions, Some(2));
+    let feed = db::feed(&db.pool, alice, None, 20).await.unwrap();
+    assert!(feed.events.iter().all(|e| e.question != Some(questions[1])));
+
+    // a rejected question stays rejected when taken back and applied again
+    db::remove_question_to_vtuber(&db.pool, questions[1], vtuber)
+        .await
+        .unwrap();
+    db::apply_question_to_vtuber(&db.pool, questions[1], vtuber)
+        .await
+        .unwrap();
+    let application = db::get_application(&db.pool, questions[1], vtuber)
+        .await
+        .unwrap();
+    assert_eq!(application.status, ApplicationStatus::Rejected);
+    assert!(application.reviewed.is_some());
+
+    db::review_application(
+        &db.pool,
+        questions[0],
+        vtuber,
+        ApplicationStatus::Hidden,
+        true,
+    )
+    .await
+    .unwrap();
+    let application = db::get_application(&db.pool, questions[0], vtuber)
+        .await
+        .unwrap();
+    assert!(!application.pinned);
+    let res =
+        db::review_application(&db.pool, own, alice, ApplicationStatus::Approved, false).await;
+    assert!(matches!(res, Err(Error::NotFound)));
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_quiz_attempt() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, alice_cookie) = register(&app, "alice").await;
+    let (vtuber, vtuber_cookie) = register(&app, "vtuber").await;
+    db::set_user_role(&db.pool, vtuber, UserRole::Vtuber)
+        .await
+        .unwrap();
+    let mut questions = Vec::new();
+    for _ in 0..3 {
+        let qid = create_question(&app, &vtuber_cookie, false).await["id"]
+            .as_i64()
+            .unwrap() as i32;
+        questions.push(qid);
+    }
+    let req = TestRequest::post()
+        .uri("/api/user/self/webhooks")
+        .cookie(vtuber_cookie.clone())
+        .set_json(json!({ "url": "http://203.0.113.7/hook" }))
+        .to_request();
+    let webhook = call(&app, req).await.body["id"].clone();
+
+    let req = TestRequest::post()
+        .uri("/api/quiz-set")
+        .cookie(vtuber_cookie.clone())
+        .set_json(json!({
+            "vtuber": vtuber,
+            "name": "arithmetic",
+            "description": "the basics",
+            "questions": &questions[..2],
+            "time_limit_secs": 600,
+            "pass_mark": 2,
+            "draft": false
+        }))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["official"], true);
+    assert_eq!(resp.body["questions"], json!(&questions[..2]));
+    let quiz_set = resp.body["id"].as_i64().unwrap();
+
+    // the answers of asked questions are hidden from others
+    for (qid, answer) in [(questions[0], json!([])), (questions[2], json!([0]))] {
+        let req = TestRequest::get()
+            .uri(&format!("/api/question/{}", qid))
+            .cookie(alice_cookie.clone())
+            .to_request();
+        assert_eq!(call(&app, req).await.body["answer"], answer);
+    }
+    assert!(db::question_in_quiz_set(&db.pool, questions[1])
+        .await
+        .unwrap());
+
+    let req = TestRequest::post()
+        .uri(&format!("/api/quiz-set/{}/attempt", quiz_set))
+        .cookie(alice_cookie.clone())
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["questions"][0]["type"], "true_false");
+    assert!(resp.body["attempt"]["deadline"].is_i64());
+    let attempt = resp.body["attempt"]["id"].as_i64().unwrap();
+
+    // the attempt keeps the version it started with
+    let req = TestRequest::put()
+        .uri(&format!("/api/quiz-set/{}", quiz_set))
+        .cookie(vtuber_cookie.clone())
+        .set_json(json!({
+            "vtuber": vtuber,
+            "name": "arithmetic",
+            "questions": questions,
+            "pass_mark": 3,
+            "draft": false
+        }))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.body["version"], 2);
+    assert_eq!(resp.body["description"], "");
+    assert_eq!(resp.body["questions"], json!(questions));
+
+    let submit = || {
+        TestRequest::post()
+            .uri(&format!("/api/attempt/{}/answers", attempt))
+            .cookie(alice_cookie.clone())
+            .set_json(json!({ "answers": [[0], [0]] }))
+            .to_request()
+    };
+    let resp = call(&app, submit()).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["version"], 1);
+    assert_eq!(resp.body["correct"], json!([true, true]));
+    assert_eq!(resp.body["score"], 2);
+    assert_eq!(resp.body["passed"], true);
+    assert!(resp.body["finished"].is_i64());
+    let resp = call(&app, submit()).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+
+    let attempt = db::get_attempt(&db.pool, attempt).await.unwrap();
+    assert_eq!(attempt.taker, alice);
+    assert_eq!(attempt.score, Some(2));
+
+    // the vtuber of the quiz set is told about the finished attempt and the
+    // leaderboard it changed
+    let deliveries = || {
+        TestRequest::get()
+            .uri(&format!("/api/user/self/webhooks/{}/deliveries", webhook))
+            .cookie(vtuber_cookie.clone())
+            .to_request()
+    };
+    let resp = call(&app, deliveries()).await;
+    let delivery = &resp.body["deliveries"][1];
+    assert_eq!(delivery["event"], "attempt_finished");
+    assert_eq!(delivery["payload"]["quiz_set"], quiz_set);
+    assert_eq!(delivery["payload"]["taker"], alice);
+    assert_eq!(delivery["payload"]["score"], 2);
+    assert_eq!(delivery["payload"]["passed"], true);
+    let delivery = &resp.body["deliveries"][0];
+    assert_eq!(delivery["event"], "leaderboard_changed");
+    assert_eq!(delivery["payload"]["quiz_set"], quiz_set);
+    assert_eq!(delivery["payload"]["entries"][0]["taker"], alice);
+    assert_eq!(delivery["payload"]["entries"][0]["score"], 2);
+
+    // a worse attempt leaves the leaderboard as it was
+    let worse = db::start_attempt(&db.pool, quiz_set as i32, 2, 3, alice, &questions, None)
+        .await
+        .unwrap();
+    db::finish_attempt(&db.pool, worse.id, &[true, false, false], true)
+        .await
+        .unwrap();
+    let resp = call(&app, deliveries()).await;
+    assert_eq!(resp.body["deliveries"].as_array().unwrap().len(), 3);
+    assert_eq!(resp.body["deliveries"][0]["event"], "attempt_finished");
+    let req = TestRequest::get()
+        .uri(&format!("/api/quiz-set/{}/leaderboard", quiz_set))
+        .to_request();
+    let resp = call(&app, req).await;
+    assert_eq!(resp.body["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(resp.body["entries"][0]["taker_name"], "alice");
+    assert_eq!(resp.body["entries"][0]["score"], 2);
+
+    // the shared result reaches the feed of the followers of the taker
+    db::follow(&db.pool, vtuber, alice, false).await.unwrap();
+    let feed = db::feed(&db.pool, vtuber, None, 20).await.unwrap();
+    assert_eq!(feed.events.len(), 1);
+    assert_eq!(feed.events[0].kind, EventKind::QuizResult);
+    assert_eq!(feed.events[0].attempt, Some(worse.id));
+    assert_eq!(feed.events[0].quiz_set, Some(quiz_set as i32));
+    assert_eq!(feed.events[0].quiz_set_name.as_deref(), Some("arithmetic"));
+    assert_eq!(feed.events[0].score, Some(1));
+    assert_eq!(feed.events[0].passed, Some(false));
+    assert!(db::get_attempt(&db.pool, worse.id).await.unwrap().shared);
+
+    // deleting the quiz set deletes its attempts
+    let req = TestRequest::delete()
+        .uri(&format!("/api/quiz-set/{}", quiz_set))
+        .cookie(vtuber_cookie.clone())
+        .to_request();
+    assert_eq!(call(&app, req).await.status, StatusCode::NO_CONTENT);
+    let res = db::get_attempt(&db.pool, attempt.id).await;
+    assert!(matches!(res, Err(Error::NotFound)));
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_recovery() {
+    let db = TestDb::new().await;
+    let app = init_app!(Arc::new(PgStorage::new(db.pool.clone())));
+    let (alice, _) = register(&app, "alice").await;
+    let (bob, _) = register(&app, "bob").await;
+    let start = |username: &str| {
+        TestRequest::post()
+            .uri(&format!("/api/user/{}/recovery", username))
+            .to_request()
+    };
+
+    // nothing bound yet
+    assert_eq!(
+        call(&app, start("alice")).await.status,
+        StatusCode::NOT_FOUND
+    );
+
+    db::bind_bilibili(&db.pool, alice, 546195, "alice", "")
+        .await
+        .unwrap();
+    let res = db::bind_bilibili(&db.pool, bob, 546195, "alice", "").await;
+    assert!(matches!(res, Err(Error::ConflictBilibili)));
+    assert_eq!(db::get_bilibili(&db.pool, alice).await.unwrap().uid, 546195);
+
+    let resp = call(&app, start("alice")).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    let code = resp.body["code"].as_str().unwrap().to_string();
+    assert_eq!(db::get_recovery_code(&db.pool, alice).await.unwrap(), code);
+
+    // starting again keeps the unexpired code, so others cannot replace it
+    let resp = call(&app, start("alice")).await;
+    assert_eq!(resp.body["code"], code.as_str());
+    assert_eq!(db::get_recovery_code(&db.pool, alice).await.unwrap(), code);
+
+    // an expired code is replaced
+    sqlx::query(r#"update recovery set expires = current_timestamp where "user" = $1"#)
+        .bind(alice)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let resp = call(&app, start("alice")).await;
+    let renewed = resp.body["code"].as_str().unwrap().to_string();
+    assert_ne!(renewed, code);
+    assert_eq!(
+        db::get_recovery_code(&db.pool, alice).await.unwrap(),
+        renewed
+    );
+
+    db::delete_recovery(&db.pool, alice).await.unwrap();
+    let res = db::get_recovery_code(&db.pool, alice).await;
+    assert!(matches!(res, Err(Error::NotFound)));
+
+    // blocked users cannot recover their accounts
+    db::set_user_blocked(&db.pool, alice, true).await.unwrap();
+    let resp = call(&app, start("alice")).await;
+    assert_eq!(resp.status, StatusCode::FORBIDDEN);
+    assert_eq!(resp.body["code"], 420002);
+    let res = db::get_recovery_code(&db.pool, alice).await;
+    assert!(matches!(res, Err(Error::NotFound)));
+
+    db.close().await;
+}
+
+#[actix_web::test]
+async fn test_take_token() {
+    let db = TestDb::new().await;
+    let bucket = Bucket {
+        capacity: 2,
+        refill_secs: 60,
+    };
+    for _ in 0..2 {
+        let wait = db::take_token(&db.pool, "POST /api/question ip:unknown", bucket)
+            .await
+            .unwrap();
+        assert_eq!(wait, None);
+    }
+    let wait = db::take_token(&db.pool, "POST /api/question ip:unknown", bucket)
+        .await
+        .unwrap();
+    assert!(matches!(wait, Some(59..=60)));
+    let wait = db::take_token(&db.pool, "POST /api/question user:1", bucket)
+        .await
+        .unwrap();
+    assert_eq!(wait, None);
+
+    db.close().await;
+}
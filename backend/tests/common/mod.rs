@@ -0,0 +1,243 @@
+//! Helpers shared by the API tests.
+#![allow(dead_code)]
+
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix_http::body::MessageBody;
+use actix_http::Request;
+use actix_web::cookie::Cookie;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use vtuber_quiz_backend::bilibili::{self, AccountInfo, BilibiliClient, PostInfo};
+use vtuber_quiz_backend::config;
+use vtuber_quiz_backend::hcaptcha::{AlwaysPass, SharedCaptcha};
+use vtuber_quiz_backend::live::Broker;
+use vtuber_quiz_backend::migrate;
+use vtuber_quiz_backend::rate_limit::RateLimiter;
+
+/// Build the API app around a `SharedStorage`, with a cookie session
+/// signed by a random key and the collaborators given by `TestOptions`.
+macro_rules! init_app {
+    ($storage:expr) => {
+        init_app!($storage, crate::common::TestOptions::default())
+    };
+    ($storage:expr, $options:expr) => {{
+        let storage: vtuber_quiz_backend::storage::SharedStorage = $storage;
+        let options: crate::common::TestOptions = $options;
+        let bilibili: vtuber_quiz_backend::bilibili::SharedBilibili =
+            std::sync::Arc::new(options.bilibili);
+        actix_web::test::init_service(
+            paperclip::actix::OpenApiExt::wrap_api(
+                actix_web::App::new()
+                    .wrap(options.rate_limiter)
+                    .wrap(vtuber_quiz_backend::request_id::RequestIdMiddleware)
+                    .wrap(actix_session::SessionMiddleware::new(
+                        actix_session::storage::CookieSessionStore::default(),
+                        actix_web::cookie::Key::generate(),
+                    ))
+                    .app_data(actix_web::web::Data::new(storage))
+                    .app_data(actix_web::web::Data::new(bilibili))
+                    .app_data(actix_web::web::Data::new(options.captcha))
+                    .app_data(actix_web::web::Data::new(options.risk))
+                    .app_data(actix_web::web::Data::new(options.broker))
+                    .app_data(actix_web::web::Data::new(options.webhook))
+                    .app_data(actix_web::web::Data::new(
+                        vtuber_quiz_backend::config::Account::default(),
+                    ))
+                    .app_data(actix_web::web::Data::new(options.login)),
+            )
+            .configure(vtuber_quiz_backend::services::routes)
+            .build()
+            .route(
+                "/test/session/{id}",
+                actix_web::web::post().to(crate::common::sign_in),
+            ),
+        )
+        .await
+    }};
+}
+
+/// What the app under test talks to besides the storage. By default a
+/// `StubBilibili` knowing no posts, no rate limits, captchas always pass,
+/// whatever the default risk scoring decides, live updates only come from the
+/// test publishing them, and the default webhook and login limits.
+pub struct TestOptions {
+    pub bilibili: StubBilibili,
+    pub rate_limiter: RateLimiter,
+    pub captcha: SharedCaptcha,
+    pub risk: config::Risk,
+    pub broker: Broker,
+    pub webhook: config::Webhook,
+    pub login: config::Login,
+}
+
+impl Default for TestOptions {
+    fn default() -> Self {
+        Self {
+            bilibili: StubBilibili::default(),
+            rate_limiter: RateLimiter::default(),
+            captcha: Arc::new(AlwaysPass),
+            risk: config::Risk::default(),
+            broker: Broker::default(),
+            webhook: config::Webhook::default(),
+            login: config::Login::default(),
+        }
+    }
+}
+
+/// Bilibili API serving a fixed set of posts.
+#[derive(Default)]
+pub struct StubBilibili {
+    pub posts: Vec<PostInfo>,
+}
+
+impl StubBilibili {
+    /// Add a post `rid` by bilibili user `uid`.
+    pub fn with_post(mut self, rid: u64, uid: u64, content: &str) -> Self {
+        self.posts.push(PostInfo {
+            rid,
+            sender: AccountInfo {
+                uid,
+                name: format!("bili{}", uid),
+                sex: "保密".to_string(),
+                face: format!("https://i0.hdslb.com/bfs/face/{}.jpg", uid),
+                level: 6,
+                silence: false,
+            },
+            content: content.to_string(),
+        });
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl BilibiliClient for StubBilibili {
+    async fn post(&self, rid: u64) -> Result<PostInfo, bilibili::Error> {
+        self.posts
+            .iter()
+            .find(|p| p.rid == rid)
+            .cloned()
+            .ok_or_else(bilibili::Error::not_found)
+    }
+}
+
+/// Stands in for `login` in tests that do not go through the captcha.
+pub async fn sign_in(
+    id: actix_web::web::Path<i32>,
+    session: actix_session::Session,
+) -> actix_web::HttpResponse {
+    session.insert("user", *id).unwrap();
+    actix_web::HttpResponse::NoContent().finish()
+}
+
+/// Response of a call: status, JSON body (`Null` if empty) and the session cookie if set.
+pub struct Response {
+    pub status: StatusCode,
+    pub body: Value,
+    pub cookie: Option<Cookie<'static>>,
+}
+
+pub async fn call<S, B>(app: &S, req: Request) -> Response
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let resp = test::call_service(app, req).await;
+    let status = resp.status();
+    let cookie = resp.response().cookies().next().map(|c| c.into_owned());
+    let bytes = test::read_body(resp).await;
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+    Response {
+        status,
+        body,
+        cookie,
+    }
+}
+
+/// A database created from `DATABASE_URL` and migrated for a single test,
+/// dropped by `close`, or when the test fails before closing it.
+pub struct TestDb {
+    pub pool: PgPool,
+    name: String,
+    admin: PgConnectOptions,
+    closed: bool,
+}
+
+impl TestDb {
+    pub async fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let url = env::var("DATABASE_URL")
+            .expect("DATABASE_URL must point to a Postgres server to run API tests");
+        let admin = PgConnectOptions::from_str(&url).unwrap();
+        let name = format!(
+            "vq_test_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+        let mut conn = PgConnection::connect_with(&admin).await.unwrap();
+        conn.execute(format!(r#"create database "{}""#, name).as_str())
+            .await
+            .unwrap();
+        conn.close().await.unwrap();
+
+        let pool = PgPoolOptions::new()
+            .max_connections(2)
+            .connect_with(admin.clone().database(&name))
+            .await
+            .unwrap();
+        migrate::run(&pool).await.unwrap();
+        Self {
+            pool,
+            name,
+            admin,
+            closed: false,
+        }
+    }
+
+    pub async fn close(mut self) {
+        self.pool.close().await;
+        let mut conn = PgConnection::connect_with(&self.admin).await.unwrap();
+        conn.execute(format!(r#"drop database "{}""#, self.name).as_str())
+            .await
+            .unwrap();
+        self.closed = true;
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        // the test runtime may be gone while unwinding, so drop the database
+        // from a runtime of its own, forcing out the connections of the pool
+        let admin = self.admin.clone();
+        let sql = format!(r#"drop database if exists "{}" with (force)"#, self.name);
+        let dropped = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .ok()?;
+            runtime.block_on(async {
+                let mut conn = PgConnection::connect_with(&admin).await.ok()?;
+                conn.execute(sql.as_str()).await.ok()
+            })
+        })
+        .join();
+        if !matches!(dropped, Ok(Some(_))) {
+            eprintln!("failed to drop test database {}", self.name);
+        }
+    }
+}
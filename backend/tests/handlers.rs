@@ -0,0 +1,1693 @@
+//! Handler tests against `MemoryStorage`, no database required.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::body::MessageBody;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use chrono::Utc;
+use futures::future::poll_fn;
+use serde_json::{json, Value};
+use vtuber_quiz_backend::config::{self, RateLimitRule};
+use vtuber_quiz_backend::live::{Broker, LiveMessage};
+use vtuber_quiz_backend::rate_limit::RateLimiter;
+use vtuber_quiz_backend::storage::{
+    ApplicationRepository, BilibiliRepository, EventRepository, FollowRepository, MemoryStorage,
+    NotificationRepository, QuestionRepository, QuizRepository, UserRepository, VoteRepository,
+    WebhookRepository,
+};
+use vtuber_quiz_commons::models::*;
+
+use common::TestOptions;
+
+#[macro_use]
+mod common;
+
+macro_rules! session_for {
+    ($app:expr, $id:expr) => {{
+        let req = test::TestRequest::post()
+            .uri(&format!("/test/session/{}", $id))
+            .to_request();
+        common::call(&$app, req).await.cookie.unwrap()
+    }};
+}
+
+/// Next chunk of a streamed response body, as text.
+async fn next_chunk<B: MessageBody>(body: &mut Pin<Box<B>>) -> String {
+    let chunk = tokio::time::timeout(
+        Duration::from_secs(5),
+        poll_fn(|cx| body.as_mut().poll_next(cx)),
+    )
+    .await
+    .expect("no chunk within 5 seconds");
+    let bytes = chunk.expect("end of stream").ok().unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+fn question_request(draft: bool) -> QuestionCreationRequest {
+    serde_json::from_value(json!({
+        "content": {
+            "description": "select a prime number from those numbers",
+            "choices": ["1", "2", "4", "8"],
+            "answer": 1
+        },
+        "audiences": ["fan"],
+        "draft": draft
+    }))
+    .unwrap()
+}
+
+#[actix_web::test]
+async fn test_get_self() {
+    let storage = Arc::new(MemoryStorage::new());
+    let id = storage.create_user("alice", "password").await.unwrap();
+    let app = init_app!(storage.clone());
+
+    let req = test::TestRequest::get().uri("/api/user/self").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let cookie = session_for!(app, id);
+    let req = test::TestRequest::get()
+        .uri("/api/user/self")
+        .cookie(cookie)
+        .to_request();
+    let user: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(user["id"], id);
+    assert_eq!(user["username"], "alice");
+    assert!(user.get("password").is_none());
+}
+
+#[actix_web::test]
+async fn test_delete_question() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password").await.unwrap();
+    let bob = storage.create_user("bob", "password").await.unwrap();
+    let qid = storage
+        .create_question(alice, question_request(false))
+        .await
+        .unwrap();
+    let app = init_app!(storage.clone());
+    let uri = format!("/api/question/{}", qid);
+
+    let cookie = session_for!(app, bob);
+    let req = test::TestRequest::delete()
+        .uri(&uri)
+        .cookie(cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let cookie = session_for!(app, alice);
+    let req = test::TestRequest::delete()
+        .uri(&uri)
+        .cookie(cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_apply_question_to_vtuber() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password").await.unwrap();
+    let bob = storage.create_user("bob", "password").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    let qid = storage
+        .create_question(alice, question_request(false))
+        .await
+        .unwrap();
+    let app = init_app!(storage.clone());
+    let cookie = session_for!(app, alice);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/question/{}/apply/{}", qid, bob))
+        .cookie(cookie.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/question/{}/apply/{}", qid, vtuber))
+        .cookie(cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    // pending applications are not shown until the vtuber approves them
+    let applied = || {
+        test::TestRequest::get()
+            .uri(&format!("/api/question/{}/apply", qid))
+            .to_request()
+    };
+    let body: Vec<i32> = test::call_and_read_body_json(&app, applied()).await;
+    assert!(body.is_empty());
+    storage
+        .review_application(qid, vtuber, ApplicationStatus::Approved, false)
+        .await
+        .unwrap();
+    let body: Vec<i32> = test::call_and_read_body_json(&app, applied()).await;
+    assert_eq!(body, vec![vtuber]);
+}
+
+#[actix_web::test]
+async fn test_update_question() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password").await.unwrap();
+    let qid = storage
+        .create_question(alice, question_request(true))
+        .await
+        .unwrap();
+    let app = init_app!(storage.clone());
+    let cookie = session_for!(app, alice);
+
+    let mut question = storage.get_question(qid).await.unwrap();
+    question.draft = false;
+    question.answer = vec![5];
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/question/{}", qid))
+        .cookie(cookie.clone())
+        .set_json(&question)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    question.answer = vec![1];
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/question/{}", qid))
+        .cookie(cookie)
+        .set_json(&question)
+        .to_request();
+    let updated: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(updated["draft"], false);
+}
+
+#[actix_web::test]
+async fn test_request_id() {
+    let app = init_app!(Arc::new(MemoryStorage::new()));
+
+    let req = test::TestRequest::get()
+        .uri("/api/user/self")
+        .insert_header(("X-Request-Id", "abc-123"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("x-request-id").unwrap(), "abc-123");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], 420003);
+    assert_eq!(body["request_id"], "abc-123");
+
+    let req = test::TestRequest::get()
+        .uri("/api/question/0")
+        .insert_header(("X-Request-Id", "not a valid id"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let id = resp
+        .headers()
+        .get("x-request-id")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(id.len(), 16);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["request_id"], id.as_str());
+}
+
+#[actix_web::test]
+async fn test_change_password() {
+    let storage = Arc::new(MemoryStorage::new());
+    let id = storage.create_user("alice", "password1").await.unwrap();
+    let app = init_app!(storage.clone());
+    let change = |old: &str, new: &str| {
+        test::TestRequest::put()
+            .uri("/api/user/self/password")
+            .set_json(json!({"old_password": old, "new_password": new}))
+    };
+
+    let resp = test::call_service(&app, change("password1", "password2").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let cookie = session_for!(app, id);
+    let req = change("wrong", "password2")
+        .cookie(cookie.clone())
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["details"][0]["field"], "old_password");
+
+    let req = change("password1", "short")
+        .cookie(cookie.clone())
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["details"][0]["field"], "new_password");
+
+    let req = change("password1", "password2").cookie(cookie).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert!(storage.login("alice", "password2").await.is_ok());
+    assert!(storage.login("alice", "password1").await.is_err());
+}
+
+#[actix_web::test]
+async fn test_bind_bilibili() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let challenge = storage.create_or_replace_challenge(alice).await.unwrap();
+    let bilibili = common::StubBilibili::default()
+        .with_post(1, 546195, "no code here")
+        .with_post(2, 546195, &format!("粉丝力测试 {}", challenge));
+    let app = init_app!(
+        storage.clone(),
+        TestOptions {
+            bilibili,
+            ..Default::default()
+        }
+    );
+    let cookie = session_for!(app, alice);
+    let bind = |post_id: u64| {
+        test::TestRequest::post()
+            .uri("/api/user/self/bilibili")
+            .cookie(cookie.clone())
+            .set_json(json!({ "post_id": post_id }))
+            .to_request()
+    };
+
+    for post_id in [1, 3] {
+        let resp = test::call_service(&app, bind(post_id)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+    let body: Value = test::call_and_read_body_json(&app, bind(2)).await;
+    assert_eq!(body["uid"], 546195);
+    assert_eq!(body["user"], alice);
+    assert!(storage
+        .get_user_by_id(alice)
+        .await
+        .unwrap()
+        .challenge
+        .is_none());
+
+    // the challenge is used up
+    let resp = test::call_service(&app, bind(2)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_recovery() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    storage.create_user("bob", "password1").await.unwrap();
+    storage
+        .bind_bilibili(alice, 546195, "bili546195", "")
+        .await
+        .unwrap();
+    let code = storage.create_recovery(alice).await.unwrap();
+    assert_eq!(storage.create_recovery(alice).await.unwrap(), code);
+    let bilibili = common::StubBilibili::default()
+        .with_post(1, 2, &code)
+        .with_post(2, 546195, &format!("找回密码 {}", code));
+    let app = init_app!(
+        storage.clone(),
+        TestOptions {
+            bilibili,
+            ..Default::default()
+        }
+    );
+    let finish = |username: &str, post_id: u64, password: &str| {
+        test::TestRequest::put()
+            .uri(&format!("/api/user/{}/recovery", username))
+            .set_json(json!({"post_id": post_id, "new_password": password}))
+            .to_request()
+    };
+
+    // no recovery started for bob
+    let resp = test::call_service(&app, finish("bob", 2, "new-password1")).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    // posted by someone else
+    let resp = test::call_service(&app, finish("alice", 1, "new-password1")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, finish("alice", 2, "weak")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, finish("alice", 2, "new-password1")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let cookie = resp.response().cookies().next().unwrap().into_owned();
+    let req = test::TestRequest::get()
+        .uri("/api/user/self")
+        .cookie(cookie)
+        .to_request();
+    let user: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(user["id"], alice);
+    assert!(storage.login("alice", "new-password1").await.is_ok());
+
+    // the code can only be used once
+    let resp = test::call_service(&app, finish("alice", 2, "new-password2")).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_login_client_address() {
+    let storage = Arc::new(MemoryStorage::new());
+    storage.create_user("alice", "password1").await.unwrap();
+    let app = init_app!(
+        storage.clone(),
+        TestOptions {
+            login: config::Login {
+                trusted_proxies: vec!["10.0.0.1".parse().unwrap()],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    );
+    let login = |peer: &str, forwarded: String, username: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/api/user/{}/session", username))
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded))
+            .set_json(json!({ "password": "wrong-password" }))
+            .to_request()
+    };
+
+    // a new X-Forwarded-For per attempt does not escape the per-address limit
+    for i in 0..21 {
+        let req = login(
+            "192.0.2.1:1234",
+            format!("198.51.100.{}", i),
+            &format!("u{}", i),
+        );
+        let resp = common::call(&app, req).await;
+        assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+    }
+    let req = login("192.0.2.1:1234", "198.51.100.99".to_string(), "alice");
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.body["code"], 420004);
+
+    // while clients behind a trusted proxy are told apart by the header
+    let req = login("10.0.0.1:1234", "192.0.2.1".to_string(), "alice");
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::TOO_MANY_REQUESTS);
+    let req = login("10.0.0.1:1234", "198.51.100.1".to_string(), "alice");
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_rate_limit() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let bob = storage.create_user("bob", "password1").await.unwrap();
+    let carol = storage.create_user("carol", "password1").await.unwrap();
+    let rules = [
+        RateLimitRule {
+            route: "POST /api/user/by-id/{id}/follow".to_string(),
+            capacity: 2,
+            refill_secs: 3600,
+        },
+        RateLimitRule {
+            route: "POST /api/user".to_string(),
+            capacity: 1,
+            refill_secs: 3600,
+        },
+    ];
+    let limiter = RateLimiter::new(&rules, Arc::new(MemoryStorage::new()));
+    let app = init_app!(
+        storage.clone(),
+        TestOptions {
+            rate_limiter: limiter,
+            ..Default::default()
+        }
+    );
+    let alice_cookie = session_for!(app, alice);
+    let bob_cookie = session_for!(app, bob);
+    let follow = |cookie: &actix_web::cookie::Cookie<'static>, id: i32| {
+        test::TestRequest::post()
+            .uri(&format!("/api/user/by-id/{}/follow", id))
+            .cookie(cookie.clone())
+            .set_json(json!({}))
+            .to_request()
+    };
+
+    for id in [bob, carol] {
+        let resp = test::call_service(&app, follow(&alice_cookie, id)).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+    let resp = test::call_service(&app, follow(&alice_cookie, bob)).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "3600");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], 460000);
+    assert_eq!(body["details"]["retry_after"], 3600);
+    assert!(body["request_id"].is_string());
+
+    // other users have their own bucket, other routes are not limited
+    let resp = test::call_service(&app, follow(&bob_cookie, alice)).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/user/by-id/{}/follow", bob))
+        .cookie(alice_cookie.clone())
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::NO_CONTENT
+    );
+
+    // anonymous clients are told apart by their address, not X-Forwarded-For
+    for (i, status) in [StatusCode::OK, StatusCode::TOO_MANY_REQUESTS]
+        .iter()
+        .enumerate()
+    {
+        let req = test::TestRequest::post()
+            .uri("/api/user")
+            .peer_addr("192.0.2.1:1234".parse().unwrap())
+            .insert_header(("X-Forwarded-For", format!("198.51.100.{}", i)))
+            .set_json(json!({"username": format!("dave{}", i), "password": "password1"}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), *status);
+    }
+}
+
+#[cfg(not(feature = "bypass-hcaptcha"))]
+#[actix_web::test]
+async fn test_captcha_verifier() {
+    use std::collections::HashMap;
+    use vtuber_quiz_backend::hcaptcha::{AlwaysFail, HttpVerifier};
+
+    let register = |username: &str, captcha: Option<&str>| {
+        let mut req = test::TestRequest::post()
+            .uri("/api/user")
+            .set_json(json!({"username": username, "password": "password1"}));
+        if let Some(captcha) = captcha {
+            req = req.insert_header(("X-HCAPTCHA-KEY", captcha));
+        }
+        req.to_request()
+    };
+
+    let app = init_app!(Arc::new(MemoryStorage::new()));
+    let resp = test::call_service(&app, register("alice", None)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let app = init_app!(
+        Arc::new(MemoryStorage::new()),
+        TestOptions {
+            captcha: Arc::new(AlwaysFail),
+            ..Default::default()
+        }
+    );
+    for captcha in [None, Some("token")] {
+        let resp = test::call_service(&app, register("alice", captcha)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], 410000);
+    }
+
+    // a siteverify stub accepting the response "good"
+    let server = actix_web::HttpServer::new(|| {
+        actix_web::App::new().route(
+            "/siteverify",
+            actix_web::web::post().to(
+                |form: actix_web::web::Form<HashMap<String, String>>| async move {
+                    let success = form.get("response").map(String::as_str) == Some("good")
+                        && form.get("secret").map(String::as_str) == Some("secret");
+                    actix_web::HttpResponse::Ok().json(json!({ "success": success }))
+                },
+            ),
+        )
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let url = format!("http://{}/siteverify", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+
+    let verifier = HttpVerifier::new(url, Some("secret".to_string()), "site".to_string());
+    let app = init_app!(
+        Arc::new(MemoryStorage::new()),
+        TestOptions {
+            captcha: Arc::new(verifier),
+            ..Default::default()
+        }
+    );
+    for (captcha, status) in [
+        (None, StatusCode::FORBIDDEN),
+        (Some("bad"), StatusCode::FORBIDDEN),
+        (Some("good"), StatusCode::OK),
+    ] {
+        let resp = test::call_service(&app, register("alice", captcha)).await;
+        assert_eq!(resp.status(), status);
+    }
+}
+
+#[cfg(not(feature = "bypass-hcaptcha"))]
+#[actix_web::test]
+async fn test_captcha_risk() {
+    use vtuber_quiz_backend::config::Risk;
+    use vtuber_quiz_backend::hcaptcha::AlwaysFail;
+    use vtuber_quiz_backend::storage::LoginAttemptRepository;
+
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let bob = storage.create_user("bob", "password1").await.unwrap();
+    let options = |risk: Risk| TestOptions {
+        captcha: Arc::new(AlwaysFail),
+        risk,
+        ..Default::default()
+    };
+    let create = |cookie: &actix_web::cookie::Cookie<'static>| {
+        test::TestRequest::post()
+            .uri("/api/question")
+            .cookie(cookie.clone())
+            .set_json(question_request(false))
+            .to_request()
+    };
+
+    // new accounts are challenged by default
+    let app = init_app!(storage.clone(), options(Risk::default()));
+    let cookie = session_for!(app, alice);
+    let resp = test::call_service(&app, create(&cookie)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let app = init_app!(
+        storage.clone(),
+        options(Risk {
+            new_account_days: 0,
+            min_reputation: 0,
+            burst_capacity: 2,
+            ..Default::default()
+        })
+    );
+    let req = test::TestRequest::post()
+        .uri("/api/user")
+        .set_json(json!({"username": "carol", "password": "password1"}))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    let alice_cookie = session_for!(app, alice);
+    for _ in 0..2 {
+        let resp = test::call_service(&app, create(&alice_cookie)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let resp = test::call_service(&app, create(&alice_cookie)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], 410000);
+
+    let bob_cookie = session_for!(app, bob);
+    let resp = test::call_service(&app, create(&bob_cookie)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    for _ in 0..3 {
+        storage
+            .record_login_failure("ip:unknown", 3600)
+            .await
+            .unwrap();
+    }
+    let resp = test::call_service(&app, create(&bob_cookie)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // failed logins count against the peer address, whatever X-Forwarded-For says
+    let dave = storage.create_user("dave", "password1").await.unwrap();
+    let dave_cookie = session_for!(app, dave);
+    for _ in 0..3 {
+        storage
+            .record_login_failure("ip:192.0.2.1", 3600)
+            .await
+            .unwrap();
+    }
+    let create_from = |peer: &str| {
+        test::TestRequest::post()
+            .uri("/api/question")
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.7"))
+            .cookie(dave_cookie.clone())
+            .set_json(question_request(false))
+            .to_request()
+    };
+    let resp = test::call_service(&app, create_from("192.0.2.2:1234")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, create_from("192.0.2.1:1234")).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_follow_lists() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let bob = storage.create_user("bob", "password1").await.unwrap();
+    let carol = storage.create_user("carol", "password1").await.unwrap();
+    storage.follow(bob, alice, false).await.unwrap();
+    storage.follow(carol, alice, true).await.unwrap();
+    storage.follow(alice, bob, true).await.unwrap();
+    let app = init_app!(storage.clone());
+    let list = |uri: String, cookie: Option<&actix_web::cookie::Cookie<'static>>| {
+        let mut req = test::TestRequest::get().uri(&uri);
+        if let Some(cookie) = cookie {
+            req = req.cookie(cookie.clone());
+        }
+        req.to_request()
+    };
+    let followers = format!("/api/user/by-id/{}/followers", alice);
+
+    // carol follows privately, only she sees it
+    let body: Value = test::call_and_read_body_json(&app, list(followers.clone(), None)).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["follows"][0]["username"], "bob");
+    let carol_cookie = session_for!(app, carol);
+    let body: Value =
+        test::call_and_read_body_json(&app, list(followers.clone(), Some(&carol_cookie))).await;
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["follows"][0]["username"], "carol");
+    assert_eq!(body["follows"][0]["private"], true);
+
+    let body: Value = test::call_and_read_body_json(
+        &app,
+        list(
+            format!("{}?offset=1&limit=1", followers),
+            Some(&carol_cookie),
+        ),
+    )
+    .await;
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["follows"].as_array().unwrap().len(), 1);
+    assert_eq!(body["follows"][0]["username"], "bob");
+
+    let followees = format!("/api/user/by-id/{}/followees", alice);
+    let body: Value = test::call_and_read_body_json(&app, list(followees.clone(), None)).await;
+    assert_eq!(body["total"], 0);
+    let alice_cookie = session_for!(app, alice);
+    let body: Value =
+        test::call_and_read_body_json(&app, list(followees, Some(&alice_cookie))).await;
+    assert_eq!(body["follows"][0]["id"], bob);
+
+    // counts on the own profile include private follows of the user
+    let body: Value = test::call_and_read_body_json(
+        &app,
+        list("/api/user/self".to_string(), Some(&alice_cookie)),
+    )
+    .await;
+    assert_eq!(body["username"], "alice");
+    assert_eq!(body["followers"], 1);
+    assert_eq!(body["followees"], 1);
+
+    for query in ["limit=0", "limit=101", "offset=-1", "limit=many"] {
+        let resp = test::call_service(&app, list(format!("{}?{}", followers, query), None)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", query);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], 450000);
+    }
+    let resp =
+        test::call_service(&app, list("/api/user/by-id/0/followers".to_string(), None)).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_get_profile() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password1").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    storage
+        .bind_bilibili(
+            vtuber,
+            546195,
+            "vtuber_official",
+            "https://i0.hdslb.com/face.jpg",
+        )
+        .await
+        .unwrap();
+    let published = storage
+        .create_question(alice, question_request(false))
+        .await
+        .unwrap();
+    let draft = storage
+        .create_question(alice, question_request(true))
+        .await
+        .unwrap();
+    let deleted = storage
+        .create_question(alice, question_request(false))
+        .await
+        .unwrap();
+    storage.delete_question(deleted).await.unwrap();
+    for qid in [published, draft, deleted] {
+        storage.apply_question_to_vtuber(qid, vtuber).await.unwrap();
+    }
+    // pending applications are not counted
+    let profile = storage.get_profile(vtuber, None).await.unwrap();
+    assert_eq!(profile.applied_questions, Some(0));
+    for qid in [published, draft, deleted] {
+        storage
+            .review_application(qid, vtuber, ApplicationStatus::Approved, false)
+            .await
+            .unwrap();
+    }
+    storage.follow(alice, vtuber, true).await.unwrap();
+    let app = init_app!(storage.clone());
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/by-id/{}", alice))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["username"], "alice");
+    assert_eq!(body["role"], "normal");
+    assert_eq!(body["questions"], 1);
+    assert_eq!(body["bilibili_name"], Value::Null);
+    assert_eq!(body["applied_questions"], Value::Null);
+    assert!(body.get("password").is_none());
+
+    // alice follows privately, only she counts it
+    let req = test::TestRequest::get()
+        .uri("/api/user/vtuber")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["id"], vtuber);
+    assert_eq!(body["bilibili_name"], "vtuber_official");
+    assert_eq!(body["bilibili_avatar"], "https://i0.hdslb.com/face.jpg");
+    assert_eq!(body["questions"], 0);
+    assert_eq!(body["applied_questions"], 1);
+    assert_eq!(body["followers"], 0);
+    let cookie = session_for!(app, alice);
+    let req = test::TestRequest::get()
+        .uri("/api/user/vtuber")
+        .cookie(cookie)
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["followers"], 1);
+
+    for uri in ["/api/user/by-id/0", "/api/user/nobody"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn test_feed() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let bob = storage.create_user("bob", "password1").await.unwrap();
+    let carol = storage.create_user("carol", "password1").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password1").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    storage.follow(alice, bob, false).await.unwrap();
+    storage.follow(alice, vtuber, true).await.unwrap();
+    let published = storage
+        .create_question(bob, question_request(false))
+        .await
+        .unwrap();
+    let draft = storage
+        .create_question(bob, question_request(true))
+        .await
+        .unwrap();
+    let unfollowed = storage
+        .create_question(carol, question_request(false))
+        .await
+        .unwrap();
+    storage
+        .apply_question_to_vtuber(published, vtuber)
+        .await
+        .unwrap();
+    storage
+        .apply_question_to_vtuber(unfollowed, vtuber)
+        .await
+        .unwrap();
+    let app = init_app!(storage.clone());
+    let cookie = session_for!(app, alice);
+    let feed = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/user/self/feed{}", query))
+            .cookie(cookie.clone())
+            .to_request()
+    };
+
+    // applications reach the feed once the vtuber approves them
+    let body: Value = test::call_and_read_body_json(&app, feed("")).await;
+    assert_eq!(body["events"].as_array().unwrap().len(), 1);
+    assert_eq!(body["events"][0]["kind"], "question_published");
+    for qid in [published, unfollowed] {
+        storage
+            .review_application(qid, vtuber, ApplicationStatus::Approved, false)
+            .await
+            .unwrap();
+    }
+
+    // carol is not followed, but applied her question to a followed vtuber
+    let body: Value = test::call_and_read_body_json(&app, feed("?limit=2")).await;
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["kind"], "question_applied");
+    assert_eq!(events[0]["actor_name"], "carol");
+    assert_eq!(events[0]["vtuber_name"], "vtuber");
+    assert_eq!(events[1]["actor"], bob);
+    assert_eq!(events[1]["question"], published);
+    let next = body["next"].as_i64().unwrap();
+    let body: Value = test::call_and_read_body_json(&app, feed(&format!("?before={}", next))).await;
+    assert_eq!(body["events"].as_array().unwrap().len(), 1);
+    assert_eq!(body["events"][0]["kind"], "question_published");
+    assert_eq!(body["events"][0]["question"], published);
+    assert_eq!(body["next"], Value::Null);
+
+    // publishing a draft is an event, removing an application takes it back
+    let mut question = storage.get_question(draft).await.unwrap();
+    question.draft = false;
+    storage.update_question(question).await.unwrap();
+    storage
+        .remove_question_to_vtuber(unfollowed, vtuber)
+        .await
+        .unwrap();
+    let body: Value = test::call_and_read_body_json(&app, feed("")).await;
+    let questions: Vec<&Value> = body["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| &e["question"])
+        .collect();
+    assert_eq!(
+        questions,
+        [&json!(draft), &json!(published), &json!(published)]
+    );
+    storage.delete_question(published).await.unwrap();
+    let body: Value = test::call_and_read_body_json(&app, feed("")).await;
+    assert_eq!(body["events"].as_array().unwrap().len(), 1);
+
+    let resp = test::call_service(&app, feed("?limit=0")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::get()
+        .uri("/api/user/self/feed")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_notifications() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let bob = storage.create_user("bob", "password1").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password1").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    let qid = storage
+        .create_question(alice, question_request(false))
+        .await
+        .unwrap();
+    storage
+        .vote_to_question(bob, qid, VoteAction::FlagIncorrect)
+        .await
+        .unwrap();
+    storage
+        .vote_to_question(alice, qid, VoteAction::UpVote)
+        .await
+        .unwrap();
+    storage.follow(bob, alice, false).await.unwrap();
+    storage.follow(vtuber, alice, true).await.unwrap();
+    storage.apply_question_to_vtuber(qid, vtuber).await.unwrap();
+    let app = init_app!(storage.clone());
+    let cookie = session_for!(app, alice);
+    let get = |uri: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .cookie(cookie.clone())
+            .to_request()
+    };
+
+    // no notification for own votes and private follows
+    let body: Value =
+        test::call_and_read_body_json(&app, get("/api/user/self/notifications")).await;
+    let notifications = body["notifications"].as_array().unwrap();
+    assert_eq!(notifications.len(), 2);
+    assert_eq!(notifications[0]["kind"], "followed");
+    assert_eq!(notifications[0]["actor_name"], "bob");
+    assert_eq!(notifications[1]["kind"], "voted");
+    assert_eq!(notifications[1]["vote"], "flag_incorrect");
+    assert_eq!(notifications[1]["question"], qid);
+    let first = notifications[1]["id"].clone();
+    let body: Value =
+        test::call_and_read_body_json(&app, get("/api/user/self/notifications/unread")).await;
+    assert_eq!(body["unread"], 2);
+
+    let vtuber_notifications = storage
+        .list_notifications(vtuber, None, 20, false)
+        .await
+        .unwrap();
+    assert_eq!(vtuber_notifications.notifications.len(), 1);
+    assert_eq!(
+        vtuber_notifications.notifications[0].kind,
+        NotificationKind::Applied
+    );
+    assert_eq!(vtuber_notifications.notifications[0].actor, Some(alice));
+
+    let mark_read = |ids: Value| {
+        test::TestRequest::post()
+            .uri("/api/user/self/notifications/read")
+            .cookie(cookie.clone())
+            .set_json(ids)
+            .to_request()
+    };
+    let resp = test::call_service(&app, mark_read(json!({ "ids": [first] }))).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let body: Value = test::call_and_read_body_json(
+        &app,
+        get("/api/user/self/notifications?unread=true&limit=1"),
+    )
+    .await;
+    assert_eq!(body["notifications"][0]["kind"], "followed");
+    assert_eq!(body["notifications"][0]["read"], false);
+    let next = body["next"].as_i64().unwrap();
+    let body: Value = test::call_and_read_body_json(
+        &app,
+        get(&format!(
+            "/api/user/self/notifications?unread=true&before={}",
+            next
+        )),
+    )
+    .await;
+    assert!(body["notifications"].as_array().unwrap().is_empty());
+
+    // marking all as read leaves other users alone
+    let resp = test::call_service(&app, mark_read(json!({}))).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(storage.unread_notifications(alice).await.unwrap(), 0);
+    assert_eq!(storage.unread_notifications(vtuber).await.unwrap(), 1);
+
+    let req = test::TestRequest::get()
+        .uri("/api/user/self/notifications/unread")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_live_stream() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let bob = storage.create_user("bob", "password1").await.unwrap();
+    let carol = storage.create_user("carol", "password1").await.unwrap();
+    storage.follow(alice, bob, true).await.unwrap();
+    let broker = Broker::new(&config::Live {
+        keep_alive_secs: 1,
+        buffer: 16,
+    });
+    let app = init_app!(
+        storage.clone(),
+        TestOptions {
+            broker: broker.clone(),
+            ..Default::default()
+        }
+    );
+    let req = test::TestRequest::get()
+        .uri("/api/user/self/stream")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let cookie = session_for!(app, alice);
+    let req = test::TestRequest::get()
+        .uri("/api/user/self/stream")
+        .cookie(cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/event-stream"
+    );
+    let mut body = Box::pin(resp.into_body());
+
+    let notification = |id: i64| Notification {
+        id,
+        kind: NotificationKind::Followed,
+        actor: Some(carol),
+        actor_name: Some("carol".to_string()),
+        question: None,
+        vote: None,
+        read: false,
+        created: Utc::now(),
+    };
+    let event = |id: i64, actor: i32| FeedEvent {
+        id,
+        kind: EventKind::QuestionPublished,
+        actor,
+        actor_name: String::new(),
+        question: Some(1),
+        vtuber: None,
+        vtuber_name: None,
+        attempt: None,
+        quiz_set: None,
+        quiz_set_name: None,
+        score: None,
+        passed: None,
+        created: Utc::now(),
+    };
+    // only notifications of alice and events of users she follows
+    broker.publish(LiveMessage::Notification {
+        recipient: bob,
+        notification: notification(1),
+    });
+    broker.publish(LiveMessage::Event(event(1, carol)));
+    broker.publish(LiveMessage::Notification {
+        recipient: alice,
+        notification: notification(2),
+    });
+    broker.publish(LiveMessage::Event(event(2, bob)));
+
+    let chunk = next_chunk(&mut body).await;
+    let data = chunk
+        .strip_prefix("event: notification\ndata: ")
+        .and_then(|rest| rest.strip_suffix("\n\n"))
+        .unwrap();
+    let data: Value = serde_json::from_str(data).unwrap();
+    assert_eq!(data["id"], 2);
+    assert_eq!(data["kind"], "followed");
+    let chunk = next_chunk(&mut body).await;
+    assert!(
+        chunk.starts_with("event: event\ndata: {\"id\":2,"),
+        "{}",
+        chunk
+    );
+
+    assert_eq!(next_chunk(&mut body).await, ": keep-alive\n\n");
+}
+
+#[actix_web::test]
+async fn test_webhooks() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password1").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    let app = init_app!(
+        storage.clone(),
+        TestOptions {
+            webhook: config::Webhook {
+                max_per_user: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    );
+    let create = |cookie, url: &str| {
+        test::TestRequest::post()
+            .uri("/api/user/self/webhooks")
+            .cookie(cookie)
+            .set_json(json!({ "url": url }))
+            .to_request()
+    };
+
+    // only vtubers register webhooks
+    let cookie = session_for!(app, alice);
+    let resp = common::call(&app, create(cookie, "https://203.0.113.7/hook")).await;
+    assert_eq!(resp.status, StatusCode::FORBIDDEN);
+
+    let cookie = session_for!(app, vtuber);
+    let resp = common::call(&app, create(cookie.clone(), "ftp://203.0.113.7")).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "url");
+    // nor point to the network of the server
+    let resp = common::call(&app, create(cookie.clone(), "http://127.0.0.1:8080/hook")).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "url");
+    let resp = common::call(&app, create(cookie.clone(), "https://203.0.113.7/hook")).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["url"], "https://203.0.113.7/hook");
+    assert_eq!(resp.body["secret"].as_str().unwrap().len(), 64);
+    let webhook = resp.body["id"].as_i64().unwrap();
+    assert_eq!(
+        storage.webhook_secret(webhook as i32).as_deref(),
+        resp.body["secret"].as_str()
+    );
+    let resp = common::call(&app, create(cookie.clone(), "https://203.0.113.7/hook")).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    let resp = common::call(&app, create(cookie.clone(), "https://203.0.113.7/other")).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    let other = resp.body["id"].as_i64().unwrap();
+    let resp = common::call(&app, create(cookie.clone(), "https://203.0.113.7/third")).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri("/api/user/self/webhooks")
+        .cookie(cookie.clone())
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    assert!(body[0].get("secret").is_none());
+
+    // approving an application queues a delivery to each webhook of the
+    // vtuber, once
+    let qid = storage
+        .create_question(alice, question_request(false))
+        .await
+        .unwrap();
+    storage.apply_question_to_vtuber(qid, vtuber).await.unwrap();
+    let list_deliveries = || {
+        test::TestRequest::get()
+            .uri(&format!("/api/user/self/webhooks/{}/deliveries", webhook))
+            .cookie(cookie.clone())
+            .to_request()
+    };
+    let body: Value = test::call_and_read_body_json(&app, list_deliveries()).await;
+    assert!(body["deliveries"].as_array().unwrap().is_empty());
+    for status in [ApplicationStatus::Approved, ApplicationStatus::Approved].iter() {
+        storage
+            .review_application(qid, vtuber, *status, false)
+            .await
+            .unwrap();
+    }
+    let body: Value = test::call_and_read_body_json(&app, list_deliveries()).await;
+    let deliveries = body["deliveries"].as_array().unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["event"], "question_applied");
+    assert_eq!(deliveries[0]["status"], "pending");
+    assert_eq!(deliveries[0]["payload"]["question"], qid);
+    assert_eq!(deliveries[0]["payload"]["creator"], alice);
+    assert_eq!(deliveries[0]["delivered"], Value::Null);
+
+    // webhooks of other users are not found
+    let alice_cookie = session_for!(app, alice);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/user/self/webhooks/{}", webhook))
+        .cookie(alice_cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/user/self/webhooks/{}", webhook))
+        .cookie(cookie.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/self/webhooks/{}/deliveries", webhook))
+        .cookie(cookie.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let webhooks = storage.list_webhooks(vtuber).await.unwrap();
+    assert_eq!(webhooks.len(), 1);
+    assert_eq!(webhooks[0].id as i64, other);
+}
+
+#[actix_web::test]
+async fn test_review_applications() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password1").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    let mut questions = Vec::new();
+    for _ in 0..3 {
+        let qid = storage
+            .create_question(alice, question_request(false))
+            .await
+            .unwrap();
+        storage.apply_question_to_vtuber(qid, vtuber).await.unwrap();
+        questions.push(qid);
+    }
+    let own = storage
+        .create_question(vtuber, question_request(false))
+        .await
+        .unwrap();
+    storage.apply_question_to_vtuber(own, vtuber).await.unwrap();
+    storage.follow(alice, vtuber, false).await.unwrap();
+    let app = init_app!(storage.clone());
+    let cookie = session_for!(app, vtuber);
+    let review = |qid: i32, body: Value| {
+        test::TestRequest::put()
+            .uri(&format!("/api/user/self/applications/{}", qid))
+            .cookie(cookie.clone())
+            .set_json(body)
+            .to_request()
+    };
+    let get = |uri: String| {
+        test::TestRequest::get()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .to_request()
+    };
+
+    // new applications wait for review, except for the vtuber's own questions
+    let body: Value = test::call_and_read_body_json(
+        &app,
+        get("/api/user/self/applications?status=pending".to_string()),
+    )
+    .await;
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["applications"][0]["creator_name"], "alice");
+    let application = storage.get_application(own, vtuber).await.unwrap();
+    assert_eq!(application.status, ApplicationStatus::Approved);
+    let feed = storage.feed(alice, None, 20).await.unwrap();
+    assert!(feed
+        .events
+        .iter()
+        .all(|e| e.kind != EventKind::QuestionApplied || e.question == Some(own)));
+
+    // only approved applications can be pinned
+    let resp = common::call(&app, review(questions[0], json!({ "pinned": true }))).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "pinned");
+    let resp = test::call_service(
+        &app,
+        review(
+            questions[0],
+            json!({ "status": "approved", "pinned": true }),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp =
+        test::call_service(&app, review(questions[1], json!({ "status": "rejected" }))).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = test::call_service(&app, review(questions[2], json!({ "status": "hidden" }))).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = common::call(&app, review(questions[2], json!({ "status": "pending" }))).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+
+    // approved questions make up the quiz of the vtuber, pinned first
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/by-id/{}/applications", vtuber))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["applications"][0]["question"], questions[0]);
+    assert_eq!(body["applications"][0]["pinned"], true);
+    assert!(body["applications"][0]["reviewed"].is_i64());
+    assert_eq!(body["applications"][1]["question"], own);
+
+    // rejected and hidden applications are no longer shown elsewhere
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/question/{}/apply", questions[1]))
+        .to_request();
+    let applied: Vec<i32> = test::call_and_read_body_json(&app, req).await;
+    assert!(applied.is_empty());
+    let profile = storage.get_profile(vtuber, None).await.unwrap();
+    assert_eq!(profile.applied_questions, Some(2));
+    let feed = storage.feed(alice, None, 20).await.unwrap();
+    let applied: Vec<Option<i32>> = feed
+        .events
+        .iter()
+        .filter(|e| e.kind == EventKind::QuestionApplied)
+        .map(|e| e.question)
+        .collect();
+    assert_eq!(applied, vec![Some(questions[0]), Some(own)]);
+
+    // unpinned once no longer approved
+    let resp = test::call_service(&app, review(questions[0], json!({ "status": "hidden" }))).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let application = storage.get_application(questions[0], vtuber).await.unwrap();
+    assert!(!application.pinned);
+
+    // only vtubers review their applications
+    let resp = test::call_service(&app, review(questions[0] + 100, json!({}))).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let alice_cookie = session_for!(app, alice);
+    let req = test::TestRequest::get()
+        .uri("/api/user/self/applications")
+        .cookie(alice_cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_reapply_rejected_application() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password1").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    let qid = storage
+        .create_question(alice, question_request(false))
+        .await
+        .unwrap();
+    let app = init_app!(storage.clone());
+    let cookie = session_for!(app, alice);
+    let apply = |method: test::TestRequest| {
+        method
+            .uri(&format!("/api/question/{}/apply/{}", qid, vtuber))
+            .cookie(cookie.clone())
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, apply(test::TestRequest::post())).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    storage
+        .review_application(qid, vtuber, ApplicationStatus::Rejected, false)
+        .await
+        .unwrap();
+
+    // taking back and applying again keeps the review
+    let resp = test::call_service(&app, apply(test::TestRequest::delete())).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = test::call_service(&app, apply(test::TestRequest::post())).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let application = storage.get_application(qid, vtuber).await.unwrap();
+    assert_eq!(application.status, ApplicationStatus::Rejected);
+    assert_eq!(storage.unread_notifications(vtuber).await.unwrap(), 1);
+}
+
+#[actix_web::test]
+async fn test_shared_quiz_result() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let bob = storage.create_user("bob", "password1").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password1").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    storage.follow(alice, bob, false).await.unwrap();
+    let qid = storage
+        .create_question(vtuber, question_request(false))
+        .await
+        .unwrap();
+    let quiz_set = |draft: bool| {
+        serde_json::from_value::<QuizSetRequest>(json!({
+            "vtuber": vtuber,
+            "name": "prime numbers",
+            "questions": [qid],
+            "pass_mark": 1,
+            "draft": draft,
+        }))
+        .unwrap()
+    };
+    let published = storage
+        .create_quiz_set(vtuber, &quiz_set(false))
+        .await
+        .unwrap();
+    let draft = storage
+        .create_quiz_set(vtuber, &quiz_set(true))
+        .await
+        .unwrap();
+    let app = init_app!(storage.clone());
+    let bob_cookie = session_for!(app, bob);
+    let take = |quiz_set: i32, share: bool| {
+        let app = &app;
+        let cookie = bob_cookie.clone();
+        async move {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/quiz-set/{}/attempt", quiz_set))
+                .cookie(cookie.clone())
+                .to_request();
+            let attempt = common::call(app, req).await.body["attempt"]["id"].clone();
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/attempt/{}/answers", attempt))
+                .cookie(cookie)
+                .set_json(json!({ "answers": [[1]], "share": share }))
+                .to_request();
+            common::call(app, req).await.body
+        }
+    };
+
+    // only results shared on published quiz sets reach the feed
+    let attempt = take(published, true).await;
+    assert_eq!(attempt["shared"], true);
+    let attempt = take(published, false).await;
+    assert_eq!(attempt["shared"], false);
+    take(draft, true).await;
+    let feed = storage.feed(alice, None, 20).await.unwrap();
+    let results: Vec<&FeedEvent> = feed
+        .events
+        .iter()
+        .filter(|e| e.kind == EventKind::QuizResult)
+        .collect();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].actor, bob);
+    assert_eq!(results[0].quiz_set, Some(published));
+    assert_eq!(results[0].quiz_set_name.as_deref(), Some("prime numbers"));
+    assert_eq!(results[0].score, Some(1));
+    assert_eq!(results[0].passed, Some(true));
+
+    // the result goes along with its quiz set
+    storage.delete_quiz_set(published).await.unwrap();
+    let feed = storage.feed(alice, None, 20).await.unwrap();
+    assert!(feed.events.iter().all(|e| e.kind != EventKind::QuizResult));
+}
+
+#[actix_web::test]
+async fn test_quiz_sets() {
+    let storage = Arc::new(MemoryStorage::new());
+    let alice = storage.create_user("alice", "password1").await.unwrap();
+    let bob = storage.create_user("bob", "password1").await.unwrap();
+    let vtuber = storage.create_user("vtuber", "password1").await.unwrap();
+    storage.set_user_role(vtuber, UserRole::Vtuber).unwrap();
+    let mut questions = Vec::new();
+    for _ in 0..3 {
+        let qid = storage
+            .create_question(alice, question_request(false))
+            .await
+            .unwrap();
+        questions.push(qid);
+    }
+    let alice_draft = storage
+        .create_question(alice, question_request(true))
+        .await
+        .unwrap();
+    let bob_draft = storage
+        .create_question(bob, question_request(true))
+        .await
+        .unwrap();
+    let app = init_app!(storage.clone());
+    let alice_cookie = session_for!(app, alice);
+    let vtuber_cookie = session_for!(app, vtuber);
+    let bob_cookie = session_for!(app, bob);
+    let quiz_set = |questions: &[i32], pass_mark: i32, draft: bool| {
+        json!({
+            "vtuber": vtuber,
+            "name": "prime numbers",
+            "questions": questions,
+            "pass_mark": pass_mark,
+            "draft": draft,
+        })
+    };
+
+    // quiz sets are about vtubers, with a reachable pass mark
+    let mut not_vtuber = quiz_set(&questions, 2, false);
+    not_vtuber["vtuber"] = json!(bob);
+    let req = test::TestRequest::post()
+        .uri("/api/quiz-set")
+        .cookie(alice_cookie.clone())
+        .set_json(not_vtuber)
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "vtuber");
+    let req = test::TestRequest::post()
+        .uri("/api/quiz-set")
+        .cookie(alice_cookie.clone())
+        .set_json(quiz_set(&questions, 4, false))
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "pass_mark");
+    let req = test::TestRequest::post()
+        .uri("/api/quiz-set")
+        .cookie(alice_cookie.clone())
+        .set_json(quiz_set(&[questions[0], questions[0]], 1, false))
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "questions");
+
+    // drafts of others cannot be asked, own drafts are left out of attempts
+    let req = test::TestRequest::post()
+        .uri("/api/quiz-set")
+        .cookie(alice_cookie.clone())
+        .set_json(quiz_set(&[questions[0], bob_draft], 1, false))
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "questions");
+    let mut with_draft = questions.clone();
+    with_draft.push(alice_draft);
+    let req = test::TestRequest::post()
+        .uri("/api/quiz-set")
+        .cookie(alice_cookie.clone())
+        .set_json(quiz_set(&with_draft, 2, false))
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["official"], false);
+    assert_eq!(resp.body["version"], 1);
+    let fan_set = resp.body["id"].as_i64().unwrap();
+
+    // answers of questions asked by published quiz sets are only shown to
+    // their creator
+    let question = |cookie: Option<_>| {
+        let req = test::TestRequest::get().uri(&format!("/api/question/{}", questions[0]));
+        match cookie {
+            Some(cookie) => req.cookie(cookie).to_request(),
+            None => req.to_request(),
+        }
+    };
+    let resp = common::call(&app, question(None)).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["answer"], json!([]));
+    let resp = common::call(&app, question(Some(bob_cookie.clone()))).await;
+    assert_eq!(resp.body["answer"], json!([]));
+    let resp = common::call(&app, question(Some(alice_cookie.clone()))).await;
+    assert_eq!(resp.body["answer"], json!([1]));
+    let req = test::TestRequest::post()
+        .uri("/api/quiz-set")
+        .cookie(vtuber_cookie.clone())
+        .set_json(quiz_set(&questions[..2], 1, true))
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["official"], true);
+    let official = resp.body["id"].as_i64().unwrap();
+
+    // drafts are only visible to their owner
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/quiz-set/{}", official))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/by-id/{}/quiz-sets", vtuber))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["quiz_sets"][0]["id"], fan_set);
+    let req = test::TestRequest::get()
+        .uri("/api/user/self/quiz-sets")
+        .cookie(vtuber_cookie.clone())
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["quiz_sets"][0]["draft"], true);
+
+    // publishing bumps the version, and official quiz sets are listed first
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/quiz-set/{}", official))
+        .cookie(alice_cookie.clone())
+        .set_json(quiz_set(&questions, 3, false))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/quiz-set/{}", official))
+        .cookie(vtuber_cookie.clone())
+        .set_json(quiz_set(&questions, 3, false))
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["version"], 2);
+    assert_eq!(resp.body["questions"], json!(questions));
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/by-id/{}/quiz-sets", vtuber))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["quiz_sets"][0]["id"], official);
+
+    // attempts leave out deleted questions and drafts, and hide the answers
+    let webhook = storage
+        .create_webhook(vtuber, "http://203.0.113.7/hook", "secret")
+        .await
+        .unwrap();
+    storage.delete_question(questions[2]).await.unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/quiz-set/{}/attempt", fan_set))
+        .cookie(bob_cookie.clone())
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["questions"].as_array().unwrap().len(), 2);
+    assert!(resp.body["questions"][0].get("answer").is_none());
+    assert_eq!(resp.body["attempt"]["pass_mark"], 2);
+    assert_eq!(resp.body["attempt"]["deadline"], Value::Null);
+    let attempt = resp.body["attempt"]["id"].as_i64().unwrap();
+    let submit = |cookie, answers: Value| {
+        test::TestRequest::post()
+            .uri(&format!("/api/attempt/{}/answers", attempt))
+            .cookie(cookie)
+            .set_json(json!({ "answers": answers }))
+            .to_request()
+    };
+    let resp = common::call(&app, submit(alice_cookie.clone(), json!([[1], [1]]))).await;
+    assert_eq!(resp.status, StatusCode::FORBIDDEN);
+    let resp = common::call(&app, submit(bob_cookie.clone(), json!([[1]]))).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "answers");
+    let resp = common::call(&app, submit(bob_cookie.clone(), json!([[1], [0, 1]]))).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["correct"], json!([true, false]));
+    assert_eq!(resp.body["score"], 1);
+    assert_eq!(resp.body["passed"], false);
+    let resp = common::call(&app, submit(bob_cookie.clone(), json!([[1], [1]]))).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "attempt");
+
+    // answers submitted past the deadline score nothing
+    let late = storage
+        .start_attempt(
+            fan_set as i32,
+            1,
+            1,
+            bob,
+            &questions[..2],
+            Some(Utc::now() - chrono::Duration::minutes(1)),
+        )
+        .await
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/attempt/{}/answers", late.id))
+        .cookie(bob_cookie.clone())
+        .set_json(json!({ "answers": [[1], [1]] }))
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body["score"], 0);
+    assert_eq!(resp.body["passed"], false);
+
+    // only the attempt entering the leaderboard changes it
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/quiz-set/{}/leaderboard", fan_set))
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    let entries = resp.body["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["taker_name"], "bob");
+    assert_eq!(entries[0]["score"], 1);
+    let deliveries = storage
+        .list_deliveries(vtuber, webhook.id, None, 20)
+        .await
+        .unwrap()
+        .deliveries;
+    let events: Vec<WebhookEvent> = deliveries.iter().map(|d| d.event).collect();
+    assert_eq!(
+        events,
+        [
+            WebhookEvent::AttemptFinished,
+            WebhookEvent::LeaderboardChanged,
+            WebhookEvent::AttemptFinished
+        ]
+    );
+    assert_eq!(deliveries[1].payload["entries"], resp.body["entries"]);
+
+    // a quiz set with only deleted questions and drafts left cannot be taken
+    for qid in &questions[..2] {
+        storage.delete_question(*qid).await.unwrap();
+    }
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/quiz-set/{}/attempt", fan_set))
+        .cookie(bob_cookie.clone())
+        .to_request();
+    let resp = common::call(&app, req).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.body["details"][0]["field"], "quiz_set");
+
+    // deleting a quiz set deletes its attempts
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/quiz-set/{}", fan_set))
+        .cookie(alice_cookie.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/attempt/{}", attempt))
+        .cookie(bob_cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
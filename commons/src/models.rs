@@ -1,8 +1,9 @@
-use chrono::serde::ts_milliseconds;
+use chrono::serde::{ts_milliseconds, ts_milliseconds_option};
 use chrono::{DateTime, Utc};
 use paperclip::actix::Apiv2Schema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::fmt;
 use std::str::FromStr;
 
 #[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
@@ -16,12 +17,115 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct PasswordChangeRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct BindRequest {
+    /// id of a bilibili post containing the verify code
+    pub post_id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct RecoveryRequest {
+    /// id of a post containing the recovery code, sent by the bound bilibili account
+    pub post_id: u64,
+    pub new_password: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
 pub struct FollowRequest {
     #[serde(default = "default_false")]
     pub private: bool,
 }
 
+/// Query of paginated lists, `limit` defaults to 20 and is at most 100.
+#[derive(Debug, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct PageRequest {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Query of the feed. `before` is the `next` cursor of the previous page,
+/// `limit` is the same as in `PageRequest`.
+#[derive(Debug, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct FeedRequest {
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Query of the notifications, paged like `FeedRequest`. Only unread
+/// notifications are listed if `unread` is true.
+#[derive(Debug, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct NotificationRequest {
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+    pub unread: Option<bool>,
+}
+
+/// Notifications to mark as read, all of them if `ids` is not given.
+#[derive(Debug, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct MarkReadRequest {
+    pub ids: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct WebhookRequest {
+    pub url: String,
+}
+
+/// Query of the applications to the logged in vtuber, paged like
+/// `PageRequest`, only those in `status` if given.
+#[derive(Debug, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct ApplicationRequest {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+    pub status: Option<ApplicationStatus>,
+}
+
+/// Review of an application by its vtuber, fields left out are unchanged.
+/// Only approved applications can be pinned, and they are unpinned when no
+/// longer approved.
+#[derive(Debug, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct ReviewRequest {
+    pub status: Option<ApplicationStatus>,
+    pub pinned: Option<bool>,
+}
+
+/// A quiz set to create, or to replace one with. `questions` are ids in the
+/// order they are asked, `pass_mark` is the number of correct answers needed
+/// to pass.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct QuizSetRequest {
+    pub vtuber: i32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub questions: Vec<i32>,
+    pub time_limit_secs: Option<i32>,
+    pub pass_mark: i32,
+    pub draft: bool,
+}
+
+/// Answers of an attempt, the choices picked for each question in order.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct AnswerSheet {
+    pub answers: Vec<Vec<i32>>,
+    /// share the result in the feed of the followers of the taker
+    #[serde(default)]
+    pub share: bool,
+}
+
+/// Query of the deliveries to a webhook, paged like `FeedRequest`.
+#[derive(Debug, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct DeliveryRequest {
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
 pub struct ChallengeResponse {
     pub code: String,
@@ -88,6 +192,97 @@ pub enum QuestionType {
     MultiAnswer,
 }
 
+#[cfg_attr(feature = "backend", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "backend",
+    sqlx(type_name = "event_kind", rename_all = "snake_case")
+)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Apiv2Schema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    QuestionPublished,
+    QuestionApplied,
+    /// the actor shared the result of an attempt
+    QuizResult,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "backend",
+    sqlx(type_name = "notification_kind", rename_all = "snake_case")
+)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Apiv2Schema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// a question of the recipient was voted on or flagged
+    Voted,
+    /// a question was applied to the recipient
+    Applied,
+    /// the recipient was followed publicly
+    Followed,
+    /// a question of the recipient was deleted by a moderator
+    QuestionDeleted,
+    /// a question of the recipient was restored by a moderator
+    QuestionRestored,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "backend",
+    sqlx(type_name = "application_status", rename_all = "snake_case")
+)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Apiv2Schema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplicationStatus {
+    /// not reviewed by the vtuber yet
+    Pending,
+    /// part of the quiz of the vtuber
+    Approved,
+    Rejected,
+    /// kept out of the quiz and public lists without rejecting it
+    Hidden,
+}
+
+impl ApplicationStatus {
+    /// Whether the application is shown on the question, the vtuber and in
+    /// the feed, which only approved applications are.
+    pub fn is_public(self) -> bool {
+        self == ApplicationStatus::Approved
+    }
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "backend",
+    sqlx(type_name = "webhook_event", rename_all = "snake_case")
+)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Apiv2Schema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// a question was applied to the owner of the webhook
+    QuestionApplied,
+    /// an attempt of a quiz set of the owner of the webhook was submitted
+    AttemptFinished,
+    /// the leaderboard of a published quiz set of the owner of the webhook
+    /// changed
+    LeaderboardChanged,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "backend",
+    sqlx(type_name = "delivery_status", rename_all = "snake_case")
+)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Apiv2Schema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// not delivered yet, to be retried
+    Pending,
+    Delivered,
+    /// given up after too many attempts
+    Failed,
+}
+
 #[cfg_attr(feature = "backend", derive(sqlx::Type))]
 #[cfg_attr(
     feature = "backend",
@@ -155,6 +350,336 @@ pub struct Question {
     pub updated: DateTime<Utc>,
 }
 
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Vote {
+    pub id: i32,
+    pub voter: i32,
+    pub question: i32,
+    pub action: VoteAction,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Bilibili {
+    pub uid: i64,
+    pub name: String,
+    pub avatar: String,
+    pub user: i32,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Following {
+    pub follower: i32,
+    pub followee: i32,
+    pub private: bool,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+}
+
+/// The other side of a follow: the follower in a list of followers, the
+/// followee in a list of followees.
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct FollowEntry {
+    pub id: i32,
+    pub username: String,
+    pub private: bool,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct FollowList {
+    /// number of follows visible to the viewer, across all pages
+    pub total: i64,
+    pub follows: Vec<FollowEntry>,
+}
+
+/// An entry of the feed, with the usernames of the users involved.
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct FeedEvent {
+    pub id: i64,
+    pub kind: EventKind,
+    pub actor: i32,
+    pub actor_name: String,
+    pub question: Option<i32>,
+    pub vtuber: Option<i32>,
+    pub vtuber_name: Option<String>,
+    /// the shared attempt of a quiz result, with its quiz set and score
+    pub attempt: Option<i64>,
+    pub quiz_set: Option<i32>,
+    pub quiz_set_name: Option<String>,
+    pub score: Option<i32>,
+    pub passed: Option<bool>,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Feed {
+    /// newest first
+    pub events: Vec<FeedEvent>,
+    /// `before` of the next page, none on the last page
+    pub next: Option<i64>,
+}
+
+/// A notification, with the username of the user who triggered it, if any.
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Notification {
+    pub id: i64,
+    pub kind: NotificationKind,
+    pub actor: Option<i32>,
+    pub actor_name: Option<String>,
+    pub question: Option<i32>,
+    pub vote: Option<VoteAction>,
+    pub read: bool,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct NotificationList {
+    /// newest first
+    pub notifications: Vec<Notification>,
+    /// `before` of the next page, none on the last page
+    pub next: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct UnreadCount {
+    pub unread: i64,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+}
+
+/// A webhook as registered, with the secret signing its deliveries. The
+/// secret is not shown again.
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+/// An event sent, or to be sent, to a webhook.
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Delivery {
+    pub id: i64,
+    pub event: WebhookEvent,
+    /// `data` of the JSON posted to the webhook
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    /// HTTP status of the last attempt, if it got a response
+    pub last_status: Option<i32>,
+    pub last_error: Option<String>,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds_option")]
+    pub delivered: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct DeliveryList {
+    /// newest first
+    pub deliveries: Vec<Delivery>,
+    /// `before` of the next page, none on the last page
+    pub next: Option<i64>,
+}
+
+/// An ordered, named list of questions about a vtuber. Official quiz sets are
+/// those made by the vtuber themselves.
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct QuizSet {
+    pub id: i32,
+    pub owner: i32,
+    pub vtuber: i32,
+    pub official: bool,
+    pub name: String,
+    pub description: String,
+    pub questions: Vec<i32>,
+    /// incremented on every update
+    pub version: i32,
+    pub time_limit_secs: Option<i32>,
+    pub pass_mark: i32,
+    pub draft: bool,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds")]
+    pub updated: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct QuizSetList {
+    /// number of quiz sets listed, across all pages
+    pub total: i64,
+    /// official first, then most recently updated first
+    pub quiz_sets: Vec<QuizSet>,
+}
+
+/// A question as shown while taking a quiz, without its answer.
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct QuizQuestion {
+    pub id: i32,
+    pub description: String,
+    pub choices: Vec<String>,
+    #[serde(rename = "type")]
+    pub question_type: QuestionType,
+}
+
+/// An attempt of a quiz set, scored once submitted.
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Attempt {
+    pub id: i64,
+    pub quiz_set: i32,
+    /// version of the quiz set taken
+    pub version: i32,
+    /// pass mark of the version taken
+    pub pass_mark: i32,
+    pub taker: i32,
+    pub questions: Vec<i32>,
+    #[serde(with = "ts_milliseconds")]
+    pub started: DateTime<Utc>,
+    /// answers submitted later are not scored
+    #[serde(with = "ts_milliseconds_option")]
+    pub deadline: Option<DateTime<Utc>>,
+    #[serde(with = "ts_milliseconds_option")]
+    pub finished: Option<DateTime<Utc>>,
+    /// whether each question was answered correctly
+    pub correct: Option<Vec<bool>>,
+    pub score: Option<i32>,
+    pub passed: Option<bool>,
+    /// whether the result was shared in the feed
+    pub shared: bool,
+}
+
+/// A taker on the leaderboard of a quiz set, with their best score.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Apiv2Schema)]
+pub struct LeaderboardEntry {
+    pub taker: i32,
+    pub taker_name: String,
+    pub score: i32,
+    /// when the score was first reached
+    #[serde(with = "ts_milliseconds")]
+    pub finished: DateTime<Utc>,
+}
+
+/// The best takers of a quiz set by their best score across versions, then by
+/// who reached it first.
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Leaderboard {
+    pub quiz_set: i32,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+/// An attempt just started, with the questions to answer.
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct StartedAttempt {
+    pub attempt: Attempt,
+    pub questions: Vec<QuizQuestion>,
+}
+
+/// Private follows are only counted for the follower.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Apiv2Schema)]
+pub struct FollowCounts {
+    pub followers: i64,
+    pub followees: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct SelfProfile {
+    #[serde(flatten)]
+    pub user: User,
+    #[serde(flatten)]
+    pub follow_counts: FollowCounts,
+}
+
+/// What anyone can see of a user.
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Profile {
+    pub id: i32,
+    pub username: String,
+    pub role: UserRole,
+    pub reputation: i32,
+    /// name of the bound bilibili account
+    pub bilibili_name: Option<String>,
+    pub bilibili_avatar: Option<String>,
+    /// published questions, leaving out drafts and deleted ones
+    pub questions: i64,
+    #[serde(flatten)]
+    pub follow_counts: FollowCounts,
+    /// published questions applied to the user, only given for vtubers
+    pub applied_questions: Option<i64>,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct Application {
+    pub question: i32,
+    pub vtuber: i32,
+    pub status: ApplicationStatus,
+    pub pinned: bool,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds_option")]
+    pub reviewed: Option<DateTime<Utc>>,
+}
+
+/// A question applied to a vtuber, in a list of their applications.
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct ApplicationEntry {
+    pub question: i32,
+    pub description: String,
+    pub creator: i32,
+    pub creator_name: String,
+    pub status: ApplicationStatus,
+    pub pinned: bool,
+    #[serde(with = "ts_milliseconds")]
+    pub created: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds_option")]
+    pub reviewed: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct ApplicationList {
+    /// number of applications listed, across all pages
+    pub total: i64,
+    /// pinned first, then newest first
+    pub applications: Vec<ApplicationEntry>,
+}
+
+impl FromStr for UserRole {
+    type Err = serde_plain::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_plain::from_str(s)
+    }
+}
+
+impl fmt::Display for UserRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(serde_plain::to_string(self).unwrap().as_str())
+    }
+}
+
 impl FromStr for VoteAction {
     type Err = serde_plain::Error;
 
@@ -163,9 +688,9 @@ impl FromStr for VoteAction {
     }
 }
 
-impl ToString for VoteAction {
-    fn to_string(&self) -> String {
-        serde_plain::to_string(self).unwrap()
+impl fmt::Display for VoteAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(serde_plain::to_string(self).unwrap().as_str())
     }
 }
 
@@ -182,11 +707,16 @@ impl ChallengeResponse {
     }
 }
 
+impl PageRequest {
+    pub const DEFAULT_LIMIT: i64 = 20;
+    pub const MAX_LIMIT: i64 = 100;
+}
+
 impl QuestionCreationRequest {
     pub fn audiences(&self) -> Vec<String> {
         self.audiences
             .iter()
-            .map(|audience| serde_json::to_string(audience).unwrap())
+            .map(|audience| serde_plain::to_string(audience).unwrap())
             .collect()
     }
 }
@@ -263,6 +793,17 @@ impl QuestionContent {
     }
 }
 
+impl From<&Question> for QuizQuestion {
+    fn from(question: &Question) -> Self {
+        Self {
+            id: question.id,
+            description: question.description.clone(),
+            choices: question.choices.clone(),
+            question_type: question.question_type,
+        }
+    }
+}
+
 impl Question {
     pub fn is_valid(&self) -> bool {
         use QuestionType::*;
@@ -298,6 +839,16 @@ impl Question {
         true
     }
 
+    /// Whether `answer` picks exactly the correct choices, in any order.
+    pub fn is_correct(&self, answer: &[i32]) -> bool {
+        let mut answer = answer.to_vec();
+        answer.sort_unstable();
+        answer.dedup();
+        let mut expected = self.answer.clone();
+        expected.sort_unstable();
+        answer == expected
+    }
+
     /// panic: when the question is not valid or not a true-false question
     pub fn unwrap_true_false(&self) -> TrueFalseQuestion {
         assert_eq!(self.question_type, QuestionType::TrueFalse);
@@ -360,6 +911,28 @@ mod tests {
         assert_eq!(FlagOutdated.to_string().as_str(), "flag_outdated");
     }
 
+    #[test]
+    fn test_user_role() {
+        assert_eq!(UserRole::from_str("normal").unwrap(), UserRole::Normal);
+        assert_eq!(UserRole::from_str("vtuber").unwrap(), UserRole::Vtuber);
+        assert!(UserRole::from_str("admin").is_err());
+
+        assert_eq!(UserRole::Vtuber.to_string().as_str(), "vtuber");
+    }
+
+    #[test]
+    fn test_audiences() {
+        let req: QuestionCreationRequest = serde_json::from_str(
+            r#"{
+  "content": {"description": "1+1=2", "is_true": true},
+  "audiences": ["passenger"],
+  "draft": false
+}"#,
+        )
+        .unwrap();
+        assert_eq!(req.audiences(), vec!["passenger".to_string()]);
+    }
+
     #[test]
     fn test_true_false() {
         let tf_json: &str = r#"{
@@ -369,7 +942,7 @@ mod tests {
         let question: QuestionContent = serde_json::from_str(tf_json).unwrap();
         let true_false = question.unwrap_true_false();
         assert_eq!(true_false.description.as_str(), "1+1=3");
-        assert_eq!(true_false.is_true, false);
+        assert!(!true_false.is_true);
     }
 
     #[test]
@@ -405,4 +978,30 @@ mod tests {
         assert_eq!(multi_answer.choices.len(), 4);
         assert_eq!(multi_answer.answer, vec![0, 1, 2]);
     }
+
+    #[test]
+    fn test_is_correct() {
+        let now = Utc::now();
+        let question = Question {
+            id: 1,
+            creator: 1,
+            description: "select all words describe color".to_string(),
+            choices: vec!["black", "red", "green", "happy"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            answer: vec![0, 1, 2],
+            question_type: QuestionType::MultiAnswer,
+            audiences: vec![],
+            draft: false,
+            deleted: false,
+            created: now,
+            updated: now,
+        };
+        assert!(question.is_correct(&[2, 0, 1]));
+        assert!(question.is_correct(&[0, 1, 1, 2]));
+        assert!(!question.is_correct(&[0, 1]));
+        assert!(!question.is_correct(&[0, 1, 2, 3]));
+        assert!(!question.is_correct(&[]));
+    }
 }
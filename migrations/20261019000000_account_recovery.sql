@@ -0,0 +1,13 @@
+-- bilibili uid 已超出 integer 的范围
+alter table bilibili alter column uid type bigint;
+
+create table recovery
+(
+    -- 找回密码的用户
+    "user"  integer references "user" primary key,
+    -- 需要用绑定的 bilibili 账号发布的验证码
+    code    varchar(10)               not null,
+    created timestamptz               not null default current_timestamp,
+    -- 验证码过期时间
+    expires timestamptz               not null
+);
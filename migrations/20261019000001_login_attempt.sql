@@ -0,0 +1,11 @@
+-- 登录失败记录，按用户名和IP分别统计
+create table login_attempt
+(
+    -- "user:<用户名>" 或 "ip:<地址>"
+    key           text primary key,
+    -- 连续失败次数，登录成功或超过重置时间后清零
+    failures      integer     not null default 0,
+    last_failure  timestamptz not null default current_timestamp,
+    -- 在此之前拒绝登录，未封锁时为空
+    blocked_until timestamptz
+);
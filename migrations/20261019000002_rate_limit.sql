@@ -0,0 +1,10 @@
+-- 限流用的令牌桶，在多个后端实例之间共享
+create table rate_limit
+(
+    -- 路由加上用户或IP
+    key     text primary key,
+    -- 桶中剩余的令牌
+    tokens  double precision not null,
+    -- tokens 的更新时间
+    updated timestamptz      not null default current_timestamp
+);
@@ -0,0 +1,19 @@
+-- 动态类型：发布问题、问题被应用到 vtuber
+create type event_kind as enum ('question_published', 'question_applied');
+
+-- 关注动态，由各个写操作产生
+create table event
+(
+    id       bigserial primary key,
+    kind     event_kind                not null,
+    -- 产生动态的用户
+    actor    integer references "user" not null,
+    -- 相关的问题
+    question integer references question,
+    -- 问题被应用到的 vtuber
+    vtuber   integer references "user",
+    created  timestamptz               not null default current_timestamp
+);
+
+create index on event (actor, id);
+create index on event (vtuber, id);
@@ -0,0 +1,21 @@
+-- 通知类型：问题被投票、问题被应用到 vtuber、被关注、问题被管理员删除或恢复
+create type notification_kind as enum ('voted', 'applied', 'followed', 'question_deleted', 'question_restored');
+
+create table notification
+(
+    id        bigserial primary key,
+    -- 接收通知的用户
+    recipient integer references "user" not null,
+    kind      notification_kind         not null,
+    -- 触发通知的用户，管理操作为空
+    actor     integer references "user",
+    -- 相关的问题
+    question  integer references question,
+    -- 投票的类型，仅 voted 通知有
+    vote      vote_action,
+    -- 是否已读
+    read      boolean                   not null default false,
+    created   timestamptz               not null default current_timestamp
+);
+
+create index on notification (recipient, id);
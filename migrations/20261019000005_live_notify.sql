@@ -0,0 +1,20 @@
+-- 新的通知和动态通过 pg_notify 广播其 id，各个后端实例再推送给连接的客户端
+create function notify_new_row() returns trigger as
+$$
+begin
+    perform pg_notify(tg_table_name, new.id::text);
+    return new;
+end;
+$$ language plpgsql;
+
+create trigger notification_notify
+    after insert
+    on notification
+    for each row
+execute procedure notify_new_row();
+
+create trigger event_notify
+    after insert
+    on event
+    for each row
+execute procedure notify_new_row();
@@ -0,0 +1,39 @@
+-- 推送给 webhook 的事件：问题被应用到 vtuber
+create type webhook_event as enum ('question_applied');
+-- 推送状态：等待（重试）中、已送达、重试次数用尽
+create type delivery_status as enum ('pending', 'delivered', 'failed');
+
+-- vtuber 注册的 webhook，接收与其相关的事件
+create table webhook
+(
+    id      serial primary key,
+    owner   integer references "user" not null,
+    url     text                      not null,
+    -- HMAC 签名密钥
+    secret  text                      not null,
+    created timestamptz               not null default current_timestamp,
+    unique (owner, url)
+);
+
+-- 推送记录，同时作为待推送的队列
+create table webhook_delivery
+(
+    id           bigserial primary key,
+    webhook      integer references webhook on delete cascade not null,
+    event        webhook_event                                not null,
+    -- 事件内容 (JSON)
+    payload      text                                         not null,
+    status       delivery_status                              not null default 'pending',
+    -- 已尝试的次数
+    attempts     integer                                      not null default 0,
+    -- 下次尝试的时间
+    next_attempt timestamptz                                  not null default current_timestamp,
+    -- 上次尝试的 HTTP 状态码，或者失败原因
+    last_status  integer,
+    last_error   text,
+    created      timestamptz                                  not null default current_timestamp,
+    delivered    timestamptz
+);
+
+create index on webhook_delivery (next_attempt) where status = 'pending';
+create index on webhook_delivery (webhook, id);
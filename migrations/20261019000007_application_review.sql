@@ -0,0 +1,12 @@
+-- 问题应用到 vtuber 后的审核状态：待审核、已通过、已拒绝、已隐藏
+create type application_status as enum ('pending', 'approved', 'rejected', 'hidden');
+
+-- 已有的应用视为已通过，新的应用等待 vtuber 审核
+alter table apply_to
+    add column status   application_status not null default 'approved',
+    -- 置顶，仅已通过的应用可以置顶
+    add column pinned   boolean            not null default false,
+    -- vtuber 上次审核的时间
+    add column reviewed timestamptz;
+alter table apply_to
+    alter column status set default 'pending';
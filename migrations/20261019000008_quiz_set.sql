@@ -0,0 +1,54 @@
+-- 有序、命名的问题集，即 vtuber 的官方测试，也可以由其他策划者整理
+create table quiz_set
+(
+    id              serial primary key,
+    -- 创建者，与 vtuber 相同时为官方测试
+    owner           integer references "user" not null,
+    -- 测试所属的 vtuber
+    vtuber          integer references "user" not null,
+    name            varchar(100)              not null,
+    description     text                      not null default '',
+    -- 每次修改递增，作答记录保存所答的版本
+    version         integer                   not null default 1,
+    -- 作答时限（秒），为空则不限时
+    time_limit_secs integer,
+    -- 通过所需答对的题数
+    pass_mark       integer                   not null,
+    draft           boolean                   not null default true,
+    created         timestamptz               not null default current_timestamp,
+    updated         timestamptz               not null default current_timestamp
+);
+
+create table quiz_set_question
+(
+    quiz_set integer references quiz_set on delete cascade not null,
+    -- 题目的顺序，从 0 开始
+    position integer                                       not null,
+    question integer references question                   not null,
+    primary key (quiz_set, position),
+    unique (quiz_set, question)
+);
+
+-- 作答记录
+create table quiz_attempt
+(
+    id        bigserial primary key,
+    quiz_set  integer references quiz_set on delete cascade not null,
+    version   integer                                       not null,
+    -- 所答版本的及格题数
+    pass_mark integer                                       not null,
+    taker     integer references "user"                     not null,
+    -- 开始作答时的题目，按顺序
+    questions integer[]                                     not null,
+    started   timestamptz                                   not null default current_timestamp,
+    -- 超过时限提交的答案不得分
+    deadline  timestamptz,
+    finished  timestamptz,
+    -- 每道题是否答对，提交后才有
+    correct   boolean[],
+    score     integer,
+    passed    boolean
+);
+
+create index on quiz_set (vtuber) where not draft;
+create index on quiz_attempt (taker, id);
@@ -0,0 +1,3 @@
+-- vtuber 的测试被完成时推送给其 webhook
+-- 新的枚举值在添加它的事务提交前不能使用，故单独作为一个迁移
+alter type webhook_event add value 'attempt_finished';
@@ -0,0 +1,5 @@
+-- vtuber 的已发布测试排行榜前列变化时推送给其 webhook
+alter type webhook_event add value 'leaderboard_changed';
+
+-- 按测试统计排行榜
+create index on quiz_attempt (quiz_set, score desc, finished) where finished is not null;
@@ -0,0 +1,8 @@
+-- 动态类型：分享的测试成绩
+alter type event_kind add value 'quiz_result';
+
+-- 提交答案时选择是否将成绩分享到关注者的动态
+alter table quiz_attempt add column shared boolean not null default false;
+
+-- 分享成绩的动态所对应的作答记录
+alter table event add column attempt bigint references quiz_attempt on delete cascade;